@@ -0,0 +1,9 @@
+-- Add down migration script here
+drop table if exists job_items cascade;
+alter table jobs
+  drop column if exists started_at,
+  drop column if exists finished_at,
+  drop column if exists total_items,
+  drop column if exists processed_items,
+  drop column if exists last_error;
+drop type if exists job_item_status;
@@ -0,0 +1,29 @@
+-- Add up migration script here
+
+begin;
+--
+create type job_item_status as enum('success', 'failure');
+--
+alter table jobs
+  add column if not exists started_at timestamptz,
+  add column if not exists finished_at timestamptz,
+  add column if not exists total_items integer,
+  add column if not exists processed_items integer not null default 0,
+  add column if not exists last_error text;
+--
+--
+create table if not exists job_items (
+  id uuid not null default uuid_generate_v4() primary key,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  job_id uuid not null references jobs(id) on delete cascade,
+  item text not null,
+  status job_item_status not null,
+  reason text
+);
+create or replace trigger update_job_items_timestamp
+  before update on job_items for each row
+  execute function update_timestamp();
+--
+--
+commit;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+drop index if exists datasource_views_next_refresh_at_idx;
+alter table datasource_views
+  drop column if exists refresh_schedule,
+  drop column if exists next_refresh_at;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+
+begin;
+--
+alter table datasource_views
+  add column if not exists refresh_schedule jsonb,
+  add column if not exists next_refresh_at timestamptz;
+--
+create index if not exists datasource_views_next_refresh_at_idx
+  on datasource_views (next_refresh_at)
+  where refresh_schedule is not null;
+--
+commit;
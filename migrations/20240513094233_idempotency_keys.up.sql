@@ -0,0 +1,22 @@
+-- Add up migration script here
+
+begin;
+--
+create table if not exists idempotency_keys (
+  id uuid not null default uuid_generate_v4() primary key,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  owner text not null,
+  idempotency_key text not null,
+  request_path text not null,
+  request_hash text not null,
+  status_code integer,
+  content_type text,
+  response_body bytea,
+  unique (owner, idempotency_key)
+);
+create or replace trigger update_idempotency_keys_timestamp
+  before update on idempotency_keys for each row
+  execute function update_timestamp();
+--
+commit;
@@ -0,0 +1,11 @@
+-- Add down migration script here
+
+begin;
+--
+update job_items set status = 'success' where status = 'skipped';
+alter type job_item_status rename to job_item_status_old;
+create type job_item_status as enum('success', 'failure');
+alter table job_items alter column status type job_item_status using status::text::job_item_status;
+drop type job_item_status_old;
+--
+commit;
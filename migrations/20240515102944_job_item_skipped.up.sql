@@ -0,0 +1,7 @@
+-- Add up migration script here
+
+begin;
+--
+alter type job_item_status add value if not exists 'skipped';
+--
+commit;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+alter table exported_users
+  drop column if exists source_record_id;
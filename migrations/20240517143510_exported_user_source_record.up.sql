@@ -0,0 +1,8 @@
+-- Add up migration script here
+
+begin;
+--
+alter table exported_users
+  add column if not exists source_record_id text;
+--
+commit;
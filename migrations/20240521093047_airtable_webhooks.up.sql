@@ -0,0 +1,21 @@
+-- Add up migration script here
+
+begin;
+--
+create table if not exists airtable_webhooks (
+  id uuid not null default uuid_generate_v4() primary key,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  datasource_view_id uuid not null unique references datasource_views(id) on delete cascade,
+  base_id text not null,
+  webhook_id text not null unique,
+  mac_secret text not null,
+  cursor bigint not null default 1,
+  expires_at timestamptz,
+  syncing_until timestamptz
+);
+create or replace trigger update_airtable_webhooks_timestamp
+  before update on airtable_webhooks for each row
+  execute function update_timestamp();
+--
+commit;
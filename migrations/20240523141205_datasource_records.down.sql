@@ -0,0 +1,4 @@
+-- Add down migration script here
+alter table datasource_views
+  drop column if exists last_imported_at;
+drop table if exists datasource_records cascade;
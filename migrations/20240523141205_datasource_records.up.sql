@@ -0,0 +1,27 @@
+-- Add up migration script here
+
+begin;
+--
+create table if not exists datasource_records (
+  id uuid not null default uuid_generate_v4() primary key,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  datasource_view_id uuid not null references datasource_views(id) on delete cascade,
+  source_record_id text not null,
+  position integer not null,
+  fields jsonb not null,
+  content_hash text not null,
+  source_created_time text not null,
+  first_seen_at timestamptz not null default current_timestamp,
+  last_seen_at timestamptz not null default current_timestamp,
+  import_job_id uuid references jobs(id) on delete set null,
+  removed_at timestamptz,
+  unique (datasource_view_id, source_record_id)
+);
+create or replace trigger update_datasource_records_timestamp
+  before update on datasource_records for each row
+  execute function update_timestamp();
+--
+alter table datasource_views add column if not exists last_imported_at timestamptz;
+--
+commit;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+drop table if exists datasource_record_changes cascade;
+drop type if exists record_change_type;
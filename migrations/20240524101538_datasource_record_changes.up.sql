@@ -0,0 +1,23 @@
+-- Add up migration script here
+
+begin;
+--
+create type record_change_type as enum('added', 'removed', 'modified');
+--
+create table if not exists datasource_record_changes (
+  id uuid not null default uuid_generate_v4() primary key,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  datasource_view_id uuid not null references datasource_views(id) on delete cascade,
+  job_id uuid references jobs(id) on delete cascade,
+  source_record_id text not null,
+  change_type record_change_type not null,
+  field_changes jsonb not null
+);
+create index if not exists datasource_record_changes_view_created_at_idx
+  on datasource_record_changes (datasource_view_id, created_at);
+create or replace trigger update_datasource_record_changes_timestamp
+  before update on datasource_record_changes for each row
+  execute function update_timestamp();
+--
+commit;
@@ -0,0 +1,2 @@
+-- Add down migration script here
+-- postgres can't drop a value from an enum type, so 'file_upload' is left in place
@@ -0,0 +1,7 @@
+-- Add up migration script here
+
+begin;
+--
+alter type supported_datasource add value if not exists 'file_upload';
+--
+commit;
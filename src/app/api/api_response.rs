@@ -18,7 +18,6 @@ where
     Data(T),
 }
 
-
 #[derive(Debug, Builder)]
 pub struct ApiResponse<T>
 where
@@ -28,6 +27,15 @@ where
     pub data: ApiResponseData<T>,
 }
 
+impl ApiResponse<()> {
+    pub fn error(status_code: StatusCode, error: &str) -> Self {
+        Self {
+            status_code,
+            data: ApiResponseData::Error(error.into()),
+        }
+    }
+}
+
 impl<T> IntoResponse for ApiResponse<T>
 where
     T: Serialize + Clone,
@@ -1,5 +1,5 @@
 mod api_error;
-mod api_response;
+pub mod api_response;
 mod v1;
 
 use axum::Router;
@@ -11,3 +11,7 @@ pub fn routes(state: AppState) -> Router<()> {
 
     Router::new().with_state(state).nest("/v1", v1)
 }
+
+pub fn webhook_routes(state: AppState) -> Router<()> {
+    v1::webhook_routes(state)
+}
@@ -1,28 +1,36 @@
 use anyhow::Result;
 use axum::{
+    body::Bytes,
     extract::{Path, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
 };
 
 use crate::{
     app::{
-        api::api_response::{ApiResponseBuilder, ApiResponseData},
+        api::api_response::{ApiResponse, ApiResponseBuilder, ApiResponseData},
         errors::AppError,
+        jobs::{self, airtable_cache_key, cached_schema, run_on_worker},
+    },
+    services::airtable::{
+        bases::Bases,
+        webhooks::{self, WebhookNotification},
     },
-    services::airtable::{bases::Bases, schema::Schema},
     state::AppState,
 };
 
 pub async fn list_bases(State(state): State<AppState>) -> Result<Response, AppError> {
     let airtable = &state.airtable;
     let cache = &state.storage.cache;
+    let key = airtable_cache_key(&state, "bases");
 
-    let bases = match cache.get_json::<Bases>("accessible-bases").await? {
+    let bases = match cache.get_json::<Bases>(&key).await? {
         Some(bases) => bases,
         None => {
             let bases = airtable.list_bases().await?;
-            cache.set_json("accessible-bases", bases.clone()).await?;
+            cache
+                .set_json_with_ttl(&key, bases.clone(), state.settings.airtable_cache_ttl)
+                .await?;
             bases
         }
     };
@@ -35,16 +43,7 @@ pub async fn list_bases(State(state): State<AppState>) -> Result<Response, AppEr
 }
 
 pub async fn fetch_schema(State(state): State<AppState>, Path(base_id): Path<String>) -> Result<Response, AppError> {
-    let airtable = &state.airtable;
-    let cache = &state.storage.cache;
-    let schema = match cache.get_json::<Schema>(&format!("{base_id}-schema")).await? {
-        Some(schema) => schema,
-        None => {
-            let schema = airtable.fetch_schema(&base_id).await?;
-            cache.set_json(&format!("{base_id}-schema"), schema.clone()).await?;
-            schema
-        }
-    };
+    let schema = cached_schema(&state, &base_id).await?;
     let res = ApiResponseBuilder::default()
         .status_code(StatusCode::OK)
         .data(ApiResponseData::Data(schema))
@@ -52,3 +51,44 @@ pub async fn fetch_schema(State(state): State<AppState>, Path(base_id): Path<Str
         .into_response();
     Ok(res)
 }
+
+pub async fn invalidate_cache(State(state): State<AppState>) -> Result<Response, AppError> {
+    let cache = &state.storage.cache;
+    cache.evict_matching(&airtable_cache_key(&state, "*")).await?;
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+// Airtable only notifies that a webhook has new payloads, which are fetched and applied in the background so the
+// notification can be acknowledged straight away.
+pub async fn receive_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let db = &state.storage.db;
+
+    let Ok(notification) = serde_json::from_slice::<WebhookNotification>(&body) else {
+        return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, "invalid webhook notification").into_response());
+    };
+
+    let Some(webhook) = db.fetch_airtable_webhook(&notification.webhook.id).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
+    let signature = headers
+        .get("x-airtable-content-mac")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    if !webhooks::verify_signature(&webhook.mac_secret, &body, signature) {
+        return Ok(ApiResponse::error(StatusCode::UNAUTHORIZED, "invalid webhook signature").into_response());
+    }
+
+    tokio::spawn(run_on_worker(state.workers.clone(), async move {
+        if let Err(e) = jobs::sync_airtable_webhook(&state, &webhook.webhook_id).await {
+            log::warn!("unable to sync airtable webhook {}: {e:#}", webhook.webhook_id);
+        }
+    }));
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
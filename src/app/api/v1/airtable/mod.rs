@@ -8,5 +8,13 @@ pub fn routes(state: AppState) -> Router<()> {
     Router::new()
         .route("/base/:base_id/schema", routing::get(controllers::fetch_schema))
         .route("/bases", routing::get(controllers::list_bases))
+        .route("/cache", routing::delete(controllers::invalidate_cache))
+        .with_state(state)
+}
+
+// Routes called by airtable itself, which authenticate with a signature instead of a user's token.
+pub fn webhook_routes(state: AppState) -> Router<()> {
+    Router::new()
+        .route("/airtable", routing::post(controllers::receive_webhook))
         .with_state(state)
 }
@@ -1,40 +1,59 @@
+use std::convert::Infallible;
+
 use anyhow::{bail, Result};
 use axum::{
-    extract::{Path, Query, Request, State},
-    http::StatusCode,
-    response::{IntoResponse, Response},
+    extract::{Multipart, Path, Query, Request, State},
+    http::{header, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     Extension, Json,
 };
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
 use serde::{Deserialize, Serialize};
-use serde_json::{Map, Value};
+use serde_json::Value;
 use tokio::task;
 use uuid::Uuid;
 
 use crate::{
     app::{
         api::{
-            api_response::{ApiResponseBuilder, ApiResponseData},
-            v1::datasource::{
-                requests::AirtableDatasourceViewRequestMetadata,
-                responses::{AirtableViewData, AirtableViewDataBuilder, DatasourceViewResponse},
+            api_response::{ApiResponse, ApiResponseBuilder, ApiResponseData},
+            v1::{
+                datasource::responses::{
+                    CreateDatasourceViewResponse, DatasourceViewDataBuilder, DatasourceViewResponse, ViewStatus,
+                },
+                users::tasks::delete_workspace_users,
             },
         },
         errors::AppError,
-        jobs,
+        jobs::{self, run_on_worker, JobTracker},
     },
     services::{
-        airtable::ListRecordsOptionsBuilder,
+        airtable::errors::AirtableError,
         auth::userdata::UserData,
         storage::{
-            dto::{CreateDatasourceViewBuilder, CreateJobBuilder, CreateJobWithDatasourceBuilder, CreateUserBuilder},
-            entities::DatasourceView,
-            types::{JobStatus, JobType, SupportedDatasource},
+            dto::{CreateDatasourceViewBuilder, CreateJobBuilder, CreateUserBuilder, EditDatasourceViewBuilder},
+            entities::{DatasourceView, RecordPage},
+            errors::JobConflict,
+            types::{
+                AirtableDatasourceViewMetadata, FileUploadDatasourceViewMetadata, JobStatus, JobType, RefreshSchedule,
+                SupportedDatasource, WorkspaceDatasourceViewMetadata,
+            },
+            Sql,
         },
+        uploads::{errors::InvalidUpload, parse_upload, ParseOptions, UploadFormat, UserColumns},
     },
     state::AppState,
 };
 
-use super::requests::{CreateDatasourceViewRequest, DatasourceViewRequest};
+use super::requests::{
+    parse_record_query, CreateDatasourceViewRequest, CreateWorkspaceViewRequest, DatasourceViewRequest,
+    DeleteDatasourceViewQuery, EditDatasourceViewRequest, RecordChangesQuery, UpdateRefreshScheduleRequest,
+    UploadDatasourceViewRequest,
+};
 
 pub async fn create_airtable(
     State(state): State<AppState>,
@@ -42,7 +61,6 @@ pub async fn create_airtable(
     Json(payload): Json<CreateDatasourceViewRequest>,
 ) -> Result<Response, AppError> {
     let db = &state.storage.db;
-    let tasks = &state.tasks;
     let UserData::Auth0(user_info) = user_info;
 
     let dto = CreateUserBuilder::default()
@@ -54,60 +72,334 @@ pub async fn create_airtable(
 
     let user_id = db.create_or_fetch_user(dto).await?;
 
-    let (base, table, view, fields) = (
-        payload.metadata.base.clone(),
-        payload.metadata.table.clone(),
-        payload.metadata.view.clone(),
-        payload.metadata.fields.clone(),
-    );
+    let next_refresh_at = match next_refresh(&payload.refresh_schedule) {
+        Ok(next_refresh_at) => next_refresh_at,
+        Err(res) => return Ok(res),
+    };
+
+    let metadata = match resolve_airtable_metadata(&state, payload.metadata).await? {
+        Ok(metadata) => metadata,
+        Err(message) => return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, &message).into_response()),
+    };
 
     let dto = CreateDatasourceViewBuilder::default()
         .view_name(payload.name)
         .datasource(SupportedDatasource::Airtable)
-        .metadata(serde_json::to_value(payload.metadata)?)
+        .metadata(serde_json::to_value(&metadata)?)
         .description(payload.description)
         .user_id(Uuid::parse_str(&user_id)?)
+        .refresh_schedule(payload.refresh_schedule)
+        .next_refresh_at(next_refresh_at)
         .build()?;
 
     let new_datasource_view_id = db.create_datasource_view(dto).await?;
 
-    let dto = CreateJobWithDatasourceBuilder::default()
-        .status(JobStatus::Pending)
-        .job_type(JobType::ImportData)
-        .user_id(Uuid::parse_str(&user_id)?)
-        .metadata(serde_json::json!({"datasource_view_id": new_datasource_view_id}))
-        .datasource_view_id(Uuid::parse_str(&new_datasource_view_id)?)
+    let Some(view) = db
+        .fetch_datasource_view(Uuid::parse_str(&new_datasource_view_id)?)
+        .await?
+    else {
+        return Ok((StatusCode::INTERNAL_SERVER_ERROR).into_response());
+    };
+
+    // without a webhook the view is still kept fresh by refreshes, so failing to register one isn't fatal
+    if let Err(e) = jobs::register_airtable_webhook(&state, view.id, &metadata).await {
+        log::warn!("unable to register an airtable webhook for {}: {e:#}", view.id);
+    }
+
+    Ok(start_view_import(&state, Uuid::parse_str(&user_id)?, &view).await?)
+}
+
+// Creates a view over the Workspace directory users matching the metadata's filter, listed as the signed in admin.
+pub async fn create_google(
+    State(state): State<AppState>,
+    Extension(user_info): Extension<UserData>,
+    Json(payload): Json<CreateWorkspaceViewRequest>,
+) -> Result<Response, AppError> {
+    let db = &state.storage.db;
+    let UserData::Auth0(ref auth0_user) = user_info;
+    let admin_email = auth0_user.email.clone();
+    let user_id = current_user_id(db, user_info).await?;
+
+    let next_refresh_at = match next_refresh(&payload.refresh_schedule) {
+        Ok(next_refresh_at) => next_refresh_at,
+        Err(res) => return Ok(res),
+    };
+
+    let mut metadata = payload.metadata;
+    metadata.admin_email = admin_email;
+
+    if let Err(problems) = metadata.validate() {
+        return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, &problems.join("; ")).into_response());
+    }
+
+    let dto = CreateDatasourceViewBuilder::default()
+        .view_name(payload.name)
+        .datasource(SupportedDatasource::GoogleWorkspaceAdminDirectory)
+        .metadata(serde_json::to_value(&metadata)?)
+        .description(payload.description)
+        .user_id(user_id)
+        .refresh_schedule(payload.refresh_schedule)
+        .next_refresh_at(next_refresh_at)
         .build()?;
 
-    let (job_id, _) = db.create_job_with_datasource(dto).await.unwrap();
-    let job_uuid = Uuid::parse_str(&job_id)?;
+    let new_datasource_view_id = db.create_datasource_view(dto).await?;
 
-    let state = state.clone();
+    let Some(view) = db
+        .fetch_datasource_view(Uuid::parse_str(&new_datasource_view_id)?)
+        .await?
+    else {
+        return Ok((StatusCode::INTERNAL_SERVER_ERROR).into_response());
+    };
 
-    let handle = task::spawn(async move {
-        let db = &state.clone().storage.db;
-        let _ = match jobs::fetch_and_cache_airtable_data(
-            state,
-            job_uuid,
-            new_datasource_view_id,
-            base,
-            table,
-            view,
-            fields,
-            None,
-        )
-        .await
-        {
-            Ok(_) => db.mark_job_complete(job_uuid).await,
-            Err(_) => db.mark_job_errored(job_uuid).await,
+    Ok(start_view_import(&state, user_id, &view).await?)
+}
+
+// Creates a view from an uploaded csv or xlsx file. The rows are stored right away, so unlike the other datasources
+// there is no import job and the view is ready as soon as it is created.
+pub async fn create_upload(
+    State(state): State<AppState>,
+    Extension(user_info): Extension<UserData>,
+    mut multipart: Multipart,
+) -> Result<Response, AppError> {
+    let db = &state.storage.db;
+    let user_id = current_user_id(db, user_info).await?;
+
+    let mut request = UploadDatasourceViewRequest::default();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, &e.body_text()).into_response()),
         };
-    });
 
-    let mut guard = tasks.lock().await;
+        let name = field.name().unwrap_or_default().to_owned();
+        if name == "file" {
+            request.file_name = field.file_name().map(str::to_owned);
+            match field.bytes().await {
+                Ok(bytes) => request.file = Some(bytes.to_vec()),
+                Err(e) => return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, &e.body_text()).into_response()),
+            }
+            continue;
+        }
+
+        let value = match field.text().await {
+            Ok(value) => value,
+            Err(e) => return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, &e.body_text()).into_response()),
+        };
+        if let Err(e) = request.set(&name, value) {
+            return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, &e.to_string()).into_response());
+        }
+    }
+
+    let (Some(file_name), Some(file)) = (request.file_name, request.file) else {
+        return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, "a file is required").into_response());
+    };
+
+    let Some(format) = UploadFormat::from_file_name(&file_name) else {
+        return Ok(
+            ApiResponse::error(StatusCode::BAD_REQUEST, "only .csv and .xlsx files can be uploaded").into_response(),
+        );
+    };
+
+    let options = ParseOptions {
+        delimiter: request.delimiter,
+        has_headers: request.has_headers,
+    };
+    let parsed = match parse_upload(format, &file, &options) {
+        Ok(parsed) => parsed,
+        Err(e) if e.is::<InvalidUpload>() => {
+            return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, &e.to_string()).into_response())
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // columns that weren't mapped in the form fall back to what the headers suggest
+    let guessed = parsed.guess_user_columns();
+    let UserColumns {
+        first_name_column,
+        last_name_column,
+        email_column,
+    } = request.user_columns;
+    let metadata = FileUploadDatasourceViewMetadata {
+        file_name: file_name.clone(),
+        format,
+        delimiter: parsed.delimiter,
+        has_headers: parsed.has_headers,
+        columns: parsed.columns.clone(),
+        user_columns: UserColumns {
+            first_name_column: first_name_column.or(guessed.first_name_column),
+            last_name_column: last_name_column.or(guessed.last_name_column),
+            email_column: email_column.or(guessed.email_column),
+        },
+    };
+
+    if let Err(problems) = metadata.validate() {
+        return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, &problems.join("; ")).into_response());
+    }
 
-    guard.insert(job_id, Some(handle));
+    let dto = CreateDatasourceViewBuilder::default()
+        .view_name(request.name.unwrap_or(file_name))
+        .datasource(SupportedDatasource::FileUpload)
+        .metadata(serde_json::to_value(&metadata)?)
+        .description(request.description.unwrap_or_default())
+        .user_id(user_id)
+        .refresh_schedule(None)
+        .next_refresh_at(None)
+        .build()?;
 
-    Ok((StatusCode::CREATED).into_response())
+    let view_id = Uuid::parse_str(&db.create_datasource_view(dto).await?)?;
+
+    let records = parsed.records(&Utc::now().to_rfc3339());
+    if let Err(e) = state.storage.save_view_records(view_id, None, &records).await {
+        // a view left without its rows would pass for an empty file
+        if let Err(delete_error) = db.delete_datasource_view(view_id).await {
+            log::warn!("unable to delete upload view {view_id} after its rows failed to save: {delete_error:#}");
+        }
+        return Err(e.into());
+    }
+
+    let Some(view) = db.fetch_datasource_view(view_id).await? else {
+        return Ok((StatusCode::INTERNAL_SERVER_ERROR).into_response());
+    };
+
+    let res = ApiResponseBuilder::default()
+        .status_code(StatusCode::CREATED)
+        .data(ApiResponseData::Data(view))
+        .build()?
+        .into_response();
+    Ok(res)
+}
+
+// Starts importing a view and answers 202 with the view and its import job, for first imports and refreshes alike.
+async fn start_view_import(state: &AppState, user_id: Uuid, view: &DatasourceView) -> Result<Response> {
+    let (job_id, handle) = jobs::start_import_job(state.clone(), user_id, view).await?;
+
+    let mut guard = state.tasks.lock().await;
+
+    guard.insert(job_id.clone(), Some(handle));
+
+    let location = job_location(&job_id);
+    let res = ApiResponseBuilder::default()
+        .status_code(StatusCode::ACCEPTED)
+        .data(ApiResponseData::Data(CreateDatasourceViewResponse {
+            view_id: view.id,
+            job_id,
+        }))
+        .build()?;
+
+    Ok(([(header::LOCATION, location)], res).into_response())
+}
+
+// Checks view metadata against its base's schema, returning why it was rejected when the base or anything the
+// metadata refers to doesn't exist.
+async fn resolve_airtable_metadata(
+    state: &AppState,
+    metadata: AirtableDatasourceViewMetadata,
+) -> Result<Result<AirtableDatasourceViewMetadata, String>> {
+    let schema = match jobs::cached_schema(state, &metadata.base).await {
+        Ok(schema) => schema,
+        Err(e)
+            if e.downcast_ref::<AirtableError>()
+                .is_some_and(|e| e.status == StatusCode::NOT_FOUND) =>
+        {
+            return Ok(Err(format!("base {} was not found", metadata.base)));
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(metadata.resolve(&schema).map_err(|problems| problems.join("; ")))
+}
+
+async fn current_user_id(db: &Sql, user_info: UserData) -> Result<Uuid> {
+    let UserData::Auth0(user_info) = user_info;
+
+    let dto = CreateUserBuilder::default()
+        .email(user_info.email)
+        .first_name(user_info.nickname)
+        .last_name("")
+        .image_uri(user_info.picture)
+        .build()?;
+
+    Ok(Uuid::parse_str(&db.create_or_fetch_user(dto).await?)?)
+}
+
+// Reads edited metadata the way the view's datasource expects it, returning the metadata to store and whether it
+// changes what the view imports, or why it was rejected.
+async fn edited_metadata(
+    state: &AppState,
+    view: &DatasourceView,
+    metadata: Value,
+) -> Result<Result<(Value, bool), String>> {
+    match view.datasource {
+        SupportedDatasource::Airtable => {
+            let current = serde_json::from_value::<AirtableDatasourceViewMetadata>(view.metadata.clone())?;
+            let metadata = match serde_json::from_value::<AirtableDatasourceViewMetadata>(metadata) {
+                Ok(metadata) => metadata,
+                Err(e) => return Ok(Err(e.to_string())),
+            };
+            let metadata = match resolve_airtable_metadata(state, metadata).await? {
+                Ok(metadata) => metadata,
+                Err(message) => return Ok(Err(message)),
+            };
+
+            let source_changed = metadata.source_differs(&current);
+            Ok(Ok((serde_json::to_value(&metadata)?, source_changed)))
+        }
+        SupportedDatasource::GoogleWorkspaceAdminDirectory => {
+            let current = serde_json::from_value::<WorkspaceDatasourceViewMetadata>(view.metadata.clone())?;
+            let mut metadata = match serde_json::from_value::<WorkspaceDatasourceViewMetadata>(metadata) {
+                Ok(metadata) => metadata,
+                Err(e) => return Ok(Err(e.to_string())),
+            };
+            metadata.admin_email = current.admin_email;
+
+            if let Err(problems) = metadata.validate() {
+                return Ok(Err(problems.join("; ")));
+            }
+
+            let source_changed = metadata.filter != current.filter;
+            Ok(Ok((serde_json::to_value(&metadata)?, source_changed)))
+        }
+        SupportedDatasource::FileUpload => {
+            let mut current = serde_json::from_value::<FileUploadDatasourceViewMetadata>(view.metadata.clone())?;
+            current.user_columns = match current.user_columns.edited(metadata) {
+                Ok(user_columns) => user_columns,
+                Err(e) => return Ok(Err(e.to_string())),
+            };
+
+            if let Err(problems) = current.validate() {
+                return Ok(Err(problems.join("; ")));
+            }
+
+            Ok(Ok((serde_json::to_value(&current)?, false)))
+        }
+    }
+}
+
+const UPLOAD_NOT_REFRESHABLE: &str = "uploaded files can't be refreshed, upload the file again as a new view";
+
+// When a view with the given schedule is first due, or a 400 explaining why the schedule can't be used.
+#[allow(clippy::result_large_err)]
+fn next_refresh(schedule: &Option<RefreshSchedule>) -> Result<Option<DateTime<Utc>>, Response> {
+    let Some(schedule) = schedule else {
+        return Ok(None);
+    };
+
+    match schedule.validate().and_then(|_| schedule.next_after(Utc::now())) {
+        Ok(next_refresh_at) => Ok(Some(next_refresh_at)),
+        Err(e) => Err(ApiResponse::error(StatusCode::BAD_REQUEST, &e.to_string()).into_response()),
+    }
+}
+
+// A 403 when the view belongs to someone other than the current user.
+fn forbidden_unless_owner(view: &DatasourceView, user_id: Uuid) -> Option<Response> {
+    (view.user_id != user_id)
+        .then(|| ApiResponse::error(StatusCode::FORBIDDEN, "this view belongs to another user").into_response())
+}
+
+// Where a client can follow a job that was accepted but not finished yet.
+fn job_location(job_id: &str) -> String {
+    format!("/api/v1/jobs/{job_id}")
 }
 
 pub async fn fetch_all(State(state): State<AppState>) -> Result<Response, AppError> {
@@ -117,148 +409,431 @@ pub async fn fetch_all(State(state): State<AppState>) -> Result<Response, AppErr
     Ok((StatusCode::OK, Json(datasource_views)).into_response())
 }
 
-pub async fn fetch_airtable_data(
+pub async fn fetch_view(
     State(state): State<AppState>,
     Path(id): Path<String>,
     Extension(user_info): Extension<UserData>,
 ) -> Result<Response, AppError> {
-    let (db, cache) = (&state.storage.db, &state.storage.cache);
+    let db = &state.storage.db;
+    let user_id = current_user_id(db, user_info).await?;
 
-    let Some(data) = db.fetch_datasource_view(Uuid::parse_str(&id)?).await? else {
+    let Some(view) = db.fetch_datasource_view(Uuid::parse_str(&id)?).await? else {
         return Ok((StatusCode::NOT_FOUND).into_response());
     };
 
-    let records = match cache.get_json::<Value>(&data.id.to_string()).await? {
-        Some(Value::Array(records)) => records,
-        Some(_) => {
-            // the fuck happened here
-            cache.evict(&data.id.to_string()).await?;
-            return Ok((StatusCode::INTERNAL_SERVER_ERROR).into_response());
+    if let Some(res) = forbidden_unless_owner(&view, user_id) {
+        return Ok(res);
+    }
+
+    let res = ApiResponseBuilder::default()
+        .status_code(StatusCode::OK)
+        .data(ApiResponseData::Data(view))
+        .build()?
+        .into_response();
+    Ok(res)
+}
+
+// Edits a view's name, description or metadata. Changing which records or fields the view reads from Airtable
+// drops its cached records, moves its webhook and starts a re-import, answering 202 with the import job.
+pub async fn edit_view(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Extension(user_info): Extension<UserData>,
+    Json(payload): Json<EditDatasourceViewRequest>,
+) -> Result<Response, AppError> {
+    let db = &state.storage.db;
+    let user_id = current_user_id(db, user_info).await?;
+
+    let Some(view) = db.fetch_datasource_view(Uuid::parse_str(&id)?).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
+    if let Some(res) = forbidden_unless_owner(&view, user_id) {
+        return Ok(res);
+    }
+
+    let (metadata, source_changed) = match payload.metadata {
+        Some(metadata) => match edited_metadata(&state, &view, metadata).await? {
+            Ok(edited) => edited,
+            Err(message) => return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, &message).into_response()),
+        },
+        None => (view.metadata.clone(), false),
+    };
+
+    // an import that is already queued would still read from the old source
+    if source_changed {
+        if let Some(job_id) = db
+            .fetch_pending_view_job(
+                view.id,
+                JobType::ImportData,
+                state.settings.stale_job_after.as_secs() as i64,
+            )
+            .await?
+        {
+            return Err(JobConflict {
+                job_id,
+                job_type: JobType::ImportData,
+            }
+            .into());
         }
-        None => {
-            let state = state.clone();
+    }
+
+    let dto = EditDatasourceViewBuilder::default()
+        .view_name(payload.name.unwrap_or(view.view_name))
+        .datasource(view.datasource)
+        .description(payload.description.unwrap_or(view.description))
+        .metadata(metadata)
+        .build()?;
 
-            let metadata = serde_json::from_value::<AirtableDatasourceViewRequestMetadata>(data.metadata.clone())?;
-            let UserData::Auth0(user_info) = user_info;
+    db.edit_datasource_view(view.id, dto).await?;
 
-            let dto = CreateUserBuilder::default()
-                .email(user_info.email)
-                .first_name(user_info.nickname)
-                .last_name("")
-                .image_uri(user_info.picture)
-                .build()?;
+    let Some(view) = db.fetch_datasource_view(view.id).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
 
-            let user_id = db.create_or_fetch_user(dto).await?;
+    if !source_changed {
+        let res = ApiResponseBuilder::default()
+            .status_code(StatusCode::OK)
+            .data(ApiResponseData::Data(view))
+            .build()?
+            .into_response();
+        return Ok(res);
+    }
 
-            let dto = CreateJobWithDatasourceBuilder::default()
+    state.storage.evict_view_records(view.id).await;
+
+    if let SupportedDatasource::Airtable = view.datasource {
+        let metadata = serde_json::from_value::<AirtableDatasourceViewMetadata>(view.metadata.clone())?;
+
+        if let Err(e) = jobs::unregister_airtable_webhook(&state, view.id).await {
+            log::warn!("unable to delete the airtable webhook of {}: {e:#}", view.id);
+        }
+        if let Err(e) = jobs::register_airtable_webhook(&state, view.id, &metadata).await {
+            log::warn!("unable to register an airtable webhook for {}: {e:#}", view.id);
+        }
+    }
+
+    let (job_id, handle) = jobs::start_import_job(state.clone(), user_id, &view).await?;
+
+    state.tasks.lock().await.insert(job_id.clone(), Some(handle));
+
+    let res = ApiResponseBuilder::default()
+        .status_code(StatusCode::ACCEPTED)
+        .data(ApiResponseData::Data(view))
+        .build()?;
+
+    Ok(([(header::LOCATION, job_location(&job_id))], res).into_response())
+}
+
+// Deletes a view along with its records, cached records and Airtable webhook. With `undoExports` the workspace users
+// exported from the view are deleted too, by an undo job that outlives the view.
+pub async fn delete_view(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DeleteDatasourceViewQuery>,
+    Extension(user_info): Extension<UserData>,
+) -> Result<Response, AppError> {
+    let db = &state.storage.db;
+    let UserData::Auth0(ref auth0_user) = user_info;
+    let admin_email = auth0_user.email.clone();
+    let user_id = current_user_id(db, user_info).await?;
+
+    let Some(view) = db.fetch_datasource_view(Uuid::parse_str(&id)?).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
+    if let Some(res) = forbidden_unless_owner(&view, user_id) {
+        return Ok(res);
+    }
+
+    let users_to_delete = match query.undo_exports {
+        true => db
+            .fetch_exported_users_by_view(view.id)
+            .await?
+            .into_iter()
+            .map(|u| u.generated_email)
+            .collect::<Vec<String>>(),
+        false => vec![],
+    };
+
+    // the job isn't linked to the view, since those links are deleted with it
+    let undo_job_id = match users_to_delete.is_empty() {
+        true => None,
+        false => {
+            let dto = CreateJobBuilder::default()
+                .user_id(user_id)
                 .status(JobStatus::Pending)
-                .job_type(JobType::ImportData)
-                .user_id(Uuid::parse_str(&user_id)?)
-                .metadata(serde_json::json!({"datasource_view_id": &data.id}))
-                .datasource_view_id(data.id)
+                .job_type(JobType::UndoExport)
+                .metadata(serde_json::json!({"datasource_view_id": view.id}))
                 .build()?;
+            Some(Uuid::parse_str(&db.create_job(dto).await?)?)
+        }
+    };
+
+    if let Err(e) = jobs::unregister_airtable_webhook(&state, view.id).await {
+        log::warn!("unable to delete the airtable webhook of {}: {e:#}", view.id);
+    }
 
-            let (job_id, _) = db.create_job_with_datasource(dto).await?;
-            let job_uuid = Uuid::parse_str(&job_id)?;
-
-            // this task is not cancellable so there is not `let handle = task::spawn(async move
-            // {...})`
-            task::spawn(async move {
-                let db = &state.clone().storage.db;
-                let _ = match jobs::fetch_and_cache_airtable_data(
-                    state,
-                    job_uuid,
-                    data.id.to_string(),
-                    metadata.base,
-                    metadata.table,
-                    metadata.view,
-                    metadata.fields,
-                    None,
-                )
-                .await
-                {
-                    Ok(_) => db.mark_job_complete(job_uuid).await,
-                    Err(_) => db.mark_job_errored(job_uuid).await,
-                };
-            });
-            vec![]
+    db.delete_datasource_view(view.id).await?;
+    state.storage.evict_view_records(view.id).await;
+
+    let Some(job_id) = undo_job_id else {
+        return Ok((StatusCode::NO_CONTENT).into_response());
+    };
+
+    let state = state.clone();
+    let tracker = JobTracker::new(state.clone(), job_id, None);
+    let heartbeat = tracker.heartbeat();
+    task::spawn(run_on_worker(state.workers.clone(), async move {
+        let _heartbeat = heartbeat;
+        if let Err(e) = delete_workspace_users(state, users_to_delete, admin_email, tracker.clone()).await {
+            let _ = tracker.fail(&format!("{e:#}")).await;
         }
+    }));
+
+    let res = ApiResponseBuilder::default()
+        .status_code(StatusCode::ACCEPTED)
+        .data(ApiResponseData::Data(serde_json::json!({"jobId": job_id})))
+        .build()?;
+
+    Ok(([(header::LOCATION, job_location(&job_id.to_string()))], res).into_response())
+}
+
+// Pages through a view's imported records, which works the same for every datasource. A view that hasn't been
+// imported yet answers 202 with its first import job.
+pub async fn fetch_view_data(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<Vec<(String, String)>>,
+    Extension(user_info): Extension<UserData>,
+) -> Result<Response, AppError> {
+    let db = &state.storage.db;
+
+    let query = match parse_record_query(&params) {
+        Ok(query) => query,
+        Err(e) => return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, &e.to_string()).into_response()),
     };
+    let user_id = current_user_id(db, user_info).await?;
+
+    let Some(data) = db.fetch_datasource_view(Uuid::parse_str(&id)?).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
+    if let Some(res) = forbidden_unless_owner(&data, user_id) {
+        return Ok(res);
+    }
+
+    let pending_job = db
+        .fetch_pending_view_job(
+            data.id,
+            JobType::ImportData,
+            state.settings.stale_job_after.as_secs() as i64,
+        )
+        .await?;
+
+    let (page, job_id) = match (data.last_imported_at, pending_job) {
+        (Some(_), job_id) => (db.query_datasource_records(data.id, &query).await?, job_id),
+        // another request already started the first import
+        (None, Some(job_id)) => (RecordPage::default(), Some(job_id)),
+        (None, None) => {
+            // this task is not cancellable so the handle is not kept in `state.tasks`
+            let job_id = match jobs::start_import_job(state.clone(), user_id, &data).await {
+                Ok((job_id, _)) => Uuid::parse_str(&job_id)?,
+                Err(e) => match e.downcast_ref::<JobConflict>() {
+                    Some(conflict) => conflict.job_id,
+                    None => return Err(e.into()),
+                },
+            };
+            (RecordPage::default(), Some(job_id))
+        }
+    };
+
+    let status = ViewStatus::of(&data, job_id.is_some(), Utc::now());
+    let datasource = data.datasource;
+
+    let view_data = DatasourceViewDataBuilder::default()
+        .data(data)
+        .status(status)
+        .job_id(job_id)
+        .records(page.records)
+        .total(page.total)
+        .matched(page.matched)
+        .page(query.offset / query.limit + 1)
+        .limit(query.limit)
+        .build()?;
+
+    let view_data = match datasource {
+        SupportedDatasource::Airtable => DatasourceViewResponse::Airtable(view_data),
+        SupportedDatasource::GoogleWorkspaceAdminDirectory => {
+            DatasourceViewResponse::GoogleWorkspaceAdminDirectory(view_data)
+        }
+        SupportedDatasource::FileUpload => DatasourceViewResponse::FileUpload(view_data),
+    };
+
+    if let (ViewStatus::Loading, Some(job_id)) = (status, job_id) {
+        let res = ApiResponseBuilder::default()
+            .status_code(StatusCode::ACCEPTED)
+            .data(ApiResponseData::Data(view_data))
+            .build()?;
+        let location = job_location(&job_id.to_string());
+        return Ok(([(header::LOCATION, location)], res).into_response());
+    }
 
-    let airtable_view_data = AirtableViewDataBuilder::default().data(data).records(records).build()?;
     let res = ApiResponseBuilder::default()
         .status_code(StatusCode::OK)
-        .data(ApiResponseData::Data(airtable_view_data))
+        .data(ApiResponseData::Data(view_data))
         .build()?
         .into_response();
 
     Ok(res)
 }
 
-pub async fn refresh_airtable_data(
+pub async fn refresh_view_data(
     State(state): State<AppState>,
     Path(id): Path<String>,
     Extension(user_info): Extension<UserData>,
 ) -> Result<Response, AppError> {
     log::info!("HERE");
     let db = &state.storage.db;
+    let user_id = current_user_id(db, user_info).await?;
 
     let Some(data) = db.fetch_datasource_view(Uuid::parse_str(&id)?).await? else {
         return Ok((StatusCode::NOT_FOUND).into_response());
     };
 
-    let UserData::Auth0(user_info) = user_info;
+    if let Some(res) = forbidden_unless_owner(&data, user_id) {
+        return Ok(res);
+    }
 
-    let dto = CreateUserBuilder::default()
-        .email(user_info.email)
-        .first_name(user_info.nickname)
-        .last_name("")
-        .image_uri(user_info.picture)
-        .build()?;
+    if let SupportedDatasource::FileUpload = data.datasource {
+        return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, UPLOAD_NOT_REFRESHABLE).into_response());
+    }
 
-    let user_id = db.create_or_fetch_user(dto).await?;
+    Ok(start_view_import(&state, user_id, &data).await?)
+}
 
-    let dto = CreateJobWithDatasourceBuilder::default()
-        .status(JobStatus::Pending)
-        .job_type(JobType::ImportData)
-        .user_id(Uuid::parse_str(&user_id)?)
-        .metadata(serde_json::json!({"datasource_view_id": &data.id}))
-        .datasource_view_id(data.id)
-        .build()?;
-    let metadata = serde_json::from_value::<AirtableDatasourceViewRequestMetadata>(data.metadata.clone())?;
-
-    let (job_id, _) = db.create_job_with_datasource(dto).await?;
-    let job_uuid = Uuid::parse_str(&job_id)?;
-
-    task::spawn(async move {
-        let db = &state.clone().storage.db;
-        let _ = match jobs::fetch_and_cache_airtable_data(
-            state,
-            job_uuid,
-            data.id.to_string(),
-            metadata.base,
-            metadata.table,
-            metadata.view,
-            metadata.fields,
-            None,
-        )
-        .await
-        {
-            Ok(_) => db.mark_job_complete(job_uuid).await,
-            Err(_) => db.mark_job_errored(job_uuid).await,
-        };
-    });
+pub async fn list_datasource_jobs(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Extension(user_info): Extension<UserData>,
+) -> Result<Response, AppError> {
+    let db = &state.storage.db;
+    let user_id = current_user_id(db, user_info).await?;
+
+    let Some(view) = db.fetch_datasource_view(Uuid::parse_str(&id)?).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
+    if let Some(res) = forbidden_unless_owner(&view, user_id) {
+        return Ok(res);
+    }
 
-    Ok((StatusCode::OK).into_response())
+    let jobs = db.fetch_datasource_view_jobs(view.id).await?;
+
+    let res = ApiResponseBuilder::default()
+        .data(ApiResponseData::Data(jobs))
+        .status_code(StatusCode::OK)
+        .build()?
+        .into_response();
+    Ok(res)
 }
 
-pub async fn list_datasource_jobs(State(state): State<AppState>, Path(id): Path<String>) -> Result<Response, AppError> {
+// Lists how the view's records changed with each import or webhook update, optionally only since a point in time.
+pub async fn list_record_changes(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<RecordChangesQuery>,
+    Extension(user_info): Extension<UserData>,
+) -> Result<Response, AppError> {
     let db = &state.storage.db;
-    let jobs = db.fetch_datasource_view_jobs(Uuid::parse_str(&id)?).await?;
+    let user_id = current_user_id(db, user_info).await?;
+
+    let Some(view) = db.fetch_datasource_view(Uuid::parse_str(&id)?).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
+    if let Some(res) = forbidden_unless_owner(&view, user_id) {
+        return Ok(res);
+    }
+
+    let changes = db.fetch_record_changes(view.id, query.since).await?;
+
+    let res = ApiResponseBuilder::default()
+        .data(ApiResponseData::Data(changes))
+        .status_code(StatusCode::OK)
+        .build()?
+        .into_response();
+    Ok(res)
+}
+
+pub async fn stream_datasource_events(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Extension(user_info): Extension<UserData>,
+) -> Result<Response, AppError> {
+    let db = &state.storage.db;
+    let user_id = current_user_id(db, user_info).await?;
+
+    let Some(view) = db.fetch_datasource_view(Uuid::parse_str(&id)?).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
+    if let Some(res) = forbidden_unless_owner(&view, user_id) {
+        return Ok(res);
+    }
+
+    let view_id = view.id;
+
+    let receiver = state.events.subscribe();
+
+    let jobs = db.fetch_datasource_view_jobs(view_id).await?;
+    let snapshot = Event::default().event("snapshot").json_data(&jobs)?;
+
+    let events = stream::once(async { Ok::<_, Infallible>(snapshot) }).chain(jobs::job_event_stream(
+        receiver,
+        move |e| e.datasource_view_id == Some(view_id),
+        false,
+    ));
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()).into_response())
+}
+
+pub async fn update_refresh_schedule(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Extension(user_info): Extension<UserData>,
+    Json(payload): Json<UpdateRefreshScheduleRequest>,
+) -> Result<Response, AppError> {
+    let db = &state.storage.db;
+    let user_id = current_user_id(db, user_info).await?;
+
+    let Some(data) = db.fetch_datasource_view(Uuid::parse_str(&id)?).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
+    if let Some(res) = forbidden_unless_owner(&data, user_id) {
+        return Ok(res);
+    }
+
+    if let SupportedDatasource::FileUpload = data.datasource {
+        return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, UPLOAD_NOT_REFRESHABLE).into_response());
+    }
+
+    let next_refresh_at = match next_refresh(&payload.refresh_schedule) {
+        Ok(next_refresh_at) => next_refresh_at,
+        Err(res) => return Ok(res),
+    };
+
+    db.set_datasource_view_schedule(data.id, payload.refresh_schedule, next_refresh_at)
+        .await?;
+
+    let Some(data) = db.fetch_datasource_view(data.id).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
 
     let res = ApiResponseBuilder::default()
-        .data(ApiResponseData::Data(jobs))
         .status_code(StatusCode::OK)
+        .data(ApiResponseData::Data(data))
         .build()?
         .into_response();
     Ok(res)
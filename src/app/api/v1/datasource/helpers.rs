@@ -1,7 +0,0 @@
-pub async fn build_initial_metadata(datasource_name: &str) {
-    match datasource_name {
-        "airtable" => todo!(),
-        "google" => todo!(),
-        _ => unimplemented!(),
-    }
-}
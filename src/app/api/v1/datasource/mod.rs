@@ -1,21 +1,50 @@
 mod controllers;
-mod helpers;
 mod requests;
 mod responses;
+#[cfg(test)]
+mod tests;
 
 use axum::{routing, Router};
 
-use crate::state::AppState;
+use crate::{app::middleware, state::AppState};
 
 pub fn routes(state: AppState) -> Router<()> {
     Router::new()
         .route("/", routing::get(controllers::fetch_all))
-        .route("/airtable", routing::post(controllers::create_airtable))
-        .route("/:id/jobs", routing::get(controllers::list_datasource_jobs))
-        .route("/airtable/:id", routing::post(controllers::fetch_airtable_data))
         .route(
-            "/airtable/:id/refresh",
-            routing::post(controllers::refresh_airtable_data),
+            "/airtable",
+            routing::post(controllers::create_airtable).route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                middleware::idempotency,
+            )),
+        )
+        .route(
+            "/google",
+            routing::post(controllers::create_google).route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                middleware::idempotency,
+            )),
+        )
+        .route(
+            "/upload",
+            routing::post(controllers::create_upload).route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                middleware::idempotency,
+            )),
+        )
+        .route(
+            "/:id",
+            routing::get(controllers::fetch_view)
+                .patch(controllers::edit_view)
+                .delete(controllers::delete_view),
         )
+        .route("/:id/jobs", routing::get(controllers::list_datasource_jobs))
+        .route("/:id/changes", routing::get(controllers::list_record_changes))
+        .route("/:id/events", routing::get(controllers::stream_datasource_events))
+        .route("/:id/schedule", routing::put(controllers::update_refresh_schedule))
+        .route("/airtable/:id", routing::post(controllers::fetch_view_data))
+        .route("/airtable/:id/refresh", routing::post(controllers::refresh_view_data))
+        .route("/google/:id", routing::post(controllers::fetch_view_data))
+        .route("/google/:id/refresh", routing::post(controllers::refresh_view_data))
         .with_state(state)
 }
@@ -1,4 +1,15 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::services::{
+    storage::{
+        dto::{FilterCondition, RecordFilter, RecordQuery, RecordSort},
+        types::{AirtableDatasourceViewMetadata, RefreshSchedule, WorkspaceDatasourceViewMetadata},
+    },
+    uploads::UserColumns,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -7,23 +18,56 @@ pub enum DatasourceViewRequest {
     Google,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct AirtableDatasourceViewRequestMetadata {
-    pub is_user_table: bool,
-    pub first_name_column: Option<String>,
-    pub last_name_column: Option<String>,
-    pub email_column: Option<String>,
-    pub base: String,
-    pub table: String,
-    pub view: String,
-    pub fields: Vec<String>,
+// The multipart form of an upload. Everything but the file is optional, and columns that aren't mapped are guessed
+// from the file's headers.
+#[derive(Debug, Default)]
+pub struct UploadDatasourceViewRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub delimiter: Option<u8>,
+    pub has_headers: Option<bool>,
+    pub user_columns: UserColumns,
+    pub file_name: Option<String>,
+    pub file: Option<Vec<u8>>,
+}
+
+impl UploadDatasourceViewRequest {
+    pub fn set(&mut self, field: &str, value: String) -> Result<()> {
+        let value = Some(value).filter(|v| !v.is_empty());
+
+        match field {
+            "name" => self.name = value,
+            "description" => self.description = value,
+            "delimiter" => {
+                self.delimiter = match value.as_deref() {
+                    None => None,
+                    Some("\\t" | "tab") => Some(b'\t'),
+                    Some(d) if d.len() == 1 && d.is_ascii() => Some(d.as_bytes()[0]),
+                    Some(_) => bail!("delimiter must be a single character"),
+                }
+            }
+            "hasHeaders" => {
+                self.has_headers = match value.as_deref() {
+                    None => None,
+                    Some("true") => Some(true),
+                    Some("false") => Some(false),
+                    Some(_) => bail!("hasHeaders must be true or false"),
+                }
+            }
+            "firstNameColumn" => self.user_columns.first_name_column = value,
+            "lastNameColumn" => self.user_columns.last_name_column = value,
+            "emailColumn" => self.user_columns.email_column = value,
+            field => bail!("unknown form field {field}"),
+        };
+
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum DatasourceViewRequestMetadata {
-    Airtable(AirtableDatasourceViewRequestMetadata),
+    Airtable(AirtableDatasourceViewMetadata),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,5 +75,120 @@ pub enum DatasourceViewRequestMetadata {
 pub struct CreateDatasourceViewRequest {
     pub name: String,
     pub description: String,
-    pub metadata: AirtableDatasourceViewRequestMetadata,
+    pub metadata: AirtableDatasourceViewMetadata,
+    pub refresh_schedule: Option<RefreshSchedule>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateWorkspaceViewRequest {
+    pub name: String,
+    pub description: String,
+    pub metadata: WorkspaceDatasourceViewMetadata,
+    pub refresh_schedule: Option<RefreshSchedule>,
+}
+
+// Fields left out of an edit keep their current values. The metadata is read as the view's datasource expects.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditDatasourceViewRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteDatasourceViewQuery {
+    // also deletes the workspace users exported from the view
+    #[serde(default)]
+    pub undo_exports: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateRefreshScheduleRequest {
+    pub refresh_schedule: Option<RefreshSchedule>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordChangesQuery {
+    pub since: Option<DateTime<Utc>>,
+}
+
+const DEFAULT_RECORDS_PER_PAGE: i64 = 100;
+const MAX_RECORDS_PER_PAGE: i64 = 1000;
+
+// Parses the query string of a records request: `page` (from 1), `limit`, `search`, a comma separated `sort` where a
+// leading `-` sorts descending, and filters written as `filter[Field][equals|contains|isEmpty]=value`.
+pub fn parse_record_query(params: &[(String, String)]) -> Result<RecordQuery> {
+    let mut page = 1;
+    let mut query = RecordQuery {
+        filters: vec![],
+        sort: vec![],
+        search: None,
+        limit: DEFAULT_RECORDS_PER_PAGE,
+        offset: 0,
+    };
+
+    for (key, value) in params {
+        match key.as_str() {
+            "page" => match value.parse::<i64>() {
+                Ok(n) if n >= 1 => page = n,
+                _ => bail!("page must be a number from 1"),
+            },
+            "limit" => match value.parse::<i64>() {
+                Ok(n) if (1..=MAX_RECORDS_PER_PAGE).contains(&n) => query.limit = n,
+                _ => bail!("limit must be a number from 1 to {MAX_RECORDS_PER_PAGE}"),
+            },
+            "search" if !value.trim().is_empty() => query.search = Some(value.trim().to_owned()),
+            "search" => {}
+            "sort" => {
+                for field in value.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+                    query.sort.push(match field.strip_prefix('-') {
+                        Some(field) => RecordSort {
+                            field: field.to_owned(),
+                            descending: true,
+                        },
+                        None => RecordSort {
+                            field: field.to_owned(),
+                            descending: false,
+                        },
+                    });
+                }
+            }
+            key => {
+                let Some((field, condition)) = key
+                    .strip_prefix("filter[")
+                    .and_then(|k| k.strip_suffix(']'))
+                    .and_then(|k| k.rsplit_once("]["))
+                else {
+                    bail!("unknown query parameter {key}");
+                };
+
+                let condition = match condition {
+                    "equals" => FilterCondition::Equals(value.clone()),
+                    "contains" => FilterCondition::Contains(value.clone()),
+                    "isEmpty" => match value.as_str() {
+                        "" | "true" => FilterCondition::IsEmpty(true),
+                        "false" => FilterCondition::IsEmpty(false),
+                        _ => bail!("isEmpty filters take true or false"),
+                    },
+                    _ => bail!("unknown filter {condition}, expected equals, contains or isEmpty"),
+                };
+
+                query.filters.push(RecordFilter {
+                    field: field.to_owned(),
+                    condition,
+                });
+            }
+        }
+    }
+
+    let Some(offset) = (page - 1).checked_mul(query.limit) else {
+        bail!("page {page} is out of range");
+    };
+    query.offset = offset;
+
+    Ok(query)
 }
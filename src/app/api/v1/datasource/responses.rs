@@ -1,23 +1,56 @@
+use chrono::{DateTime, Utc};
 use derive_builder::Builder;
 use serde::Serialize;
 use serde_json::Value;
+use uuid::Uuid;
 
-use crate::services::storage::entities::DatasourceView;
+use crate::services::{airtable::record::Record, storage::entities::DatasourceView};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewStatus {
+    Loading,
+    Ready,
+    Stale,
+}
+
+impl ViewStatus {
+    // A view is loading until its first import lands, and stale while a re-import is pending or its scheduled
+    // refresh is overdue.
+    pub fn of(view: &DatasourceView, import_pending: bool, now: DateTime<Utc>) -> Self {
+        if view.last_imported_at.is_none() {
+            ViewStatus::Loading
+        } else if import_pending || view.next_refresh_at.is_some_and(|at| at <= now) {
+            ViewStatus::Stale
+        } else {
+            ViewStatus::Ready
+        }
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Builder)]
-pub struct AirtableViewData {
+pub struct DatasourceViewData {
     data: DatasourceView,
-    records: Vec<Value>,
+    status: ViewStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job_id: Option<Uuid>,
+    records: Vec<Record<Value>>,
+    total: i64,
+    matched: i64,
+    page: i64,
+    limit: i64,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 #[serde(untagged)]
 pub enum DatasourceViewResponse {
-    Airtable(AirtableViewData),
-    GoogleWorkspaceAdminDirectory,
+    Airtable(DatasourceViewData),
+    GoogleWorkspaceAdminDirectory(DatasourceViewData),
+    FileUpload(DatasourceViewData),
 }
 
-#[derive(Debug, Serialize)]
-pub struct CreateAirtableViewResponse {
+#[derive(Clone, Debug, Serialize)]
+pub struct CreateDatasourceViewResponse {
+    pub view_id: Uuid,
     pub job_id: String,
 }
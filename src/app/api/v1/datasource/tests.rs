@@ -0,0 +1,140 @@
+use chrono::{Duration, Utc};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::services::storage::{
+    dto::{FilterCondition, RecordFilter, RecordSort},
+    entities::DatasourceView,
+    types::SupportedDatasource,
+};
+
+use super::{
+    requests::{parse_record_query, UploadDatasourceViewRequest},
+    responses::ViewStatus,
+};
+
+fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+#[test]
+pub fn test_parse_record_query_defaults() {
+    let query = parse_record_query(&[]).unwrap();
+
+    assert!(query.filters.is_empty());
+    assert!(query.sort.is_empty());
+    assert_eq!(query.search, None);
+    assert_eq!((query.limit, query.offset), (100, 0));
+}
+
+#[test]
+pub fn test_parse_record_query_params() {
+    let query = parse_record_query(&params(&[
+        ("page", "3"),
+        ("limit", "20"),
+        ("search", " ada "),
+        ("sort", "Last Name,-Hours"),
+        ("filter[Tags][equals]", "admin"),
+        ("filter[Notes[0]][contains]", "x"),
+        ("filter[Email][isEmpty]", "false"),
+    ]))
+    .unwrap();
+
+    assert_eq!((query.limit, query.offset), (20, 40));
+    assert_eq!(query.search.as_deref(), Some("ada"));
+    assert_eq!(
+        query.sort,
+        vec![
+            RecordSort {
+                field: "Last Name".into(),
+                descending: false
+            },
+            RecordSort {
+                field: "Hours".into(),
+                descending: true
+            },
+        ]
+    );
+    assert_eq!(
+        query.filters,
+        vec![
+            RecordFilter {
+                field: "Tags".into(),
+                condition: FilterCondition::Equals("admin".into())
+            },
+            RecordFilter {
+                field: "Notes[0]".into(),
+                condition: FilterCondition::Contains("x".into())
+            },
+            RecordFilter {
+                field: "Email".into(),
+                condition: FilterCondition::IsEmpty(false)
+            },
+        ]
+    );
+}
+
+#[test]
+pub fn test_parse_record_query_rejects_bad_params() {
+    for pair in [
+        ("page", "0"),
+        ("page", "9223372036854775807"),
+        ("limit", "5000"),
+        ("filter[Email][startsWith]", "a"),
+        ("filter[Email]", "a"),
+        ("filter[Email][isEmpty]", "maybe"),
+        ("cursor", "abc"),
+    ] {
+        assert!(parse_record_query(&params(&[pair])).is_err(), "{pair:?}");
+    }
+}
+
+#[test]
+pub fn test_view_status() {
+    let now = Utc::now();
+    let mut view = DatasourceView {
+        id: Uuid::new_v4(),
+        created_at: now,
+        updated_at: now,
+        user_id: Uuid::new_v4(),
+        view_name: "People".into(),
+        datasource: SupportedDatasource::Airtable,
+        description: String::new(),
+        metadata: json!({}),
+        refresh_schedule: None,
+        next_refresh_at: None,
+        last_imported_at: None,
+    };
+
+    assert_eq!(ViewStatus::of(&view, true, now), ViewStatus::Loading);
+
+    view.last_imported_at = Some(now);
+    assert_eq!(ViewStatus::of(&view, false, now), ViewStatus::Ready);
+    assert_eq!(ViewStatus::of(&view, true, now), ViewStatus::Stale);
+
+    view.next_refresh_at = Some(now + Duration::hours(1));
+    assert_eq!(ViewStatus::of(&view, false, now), ViewStatus::Ready);
+
+    view.next_refresh_at = Some(now - Duration::minutes(1));
+    assert_eq!(ViewStatus::of(&view, false, now), ViewStatus::Stale);
+}
+
+#[test]
+pub fn test_upload_request_fields() {
+    let mut request = UploadDatasourceViewRequest::default();
+    request.set("name", "Cohort".into()).unwrap();
+    request.set("description", "".into()).unwrap();
+    request.set("delimiter", "tab".into()).unwrap();
+    request.set("hasHeaders", "false".into()).unwrap();
+    request.set("emailColumn", "Column 3".into()).unwrap();
+
+    assert_eq!(request.name.as_deref(), Some("Cohort"));
+    assert_eq!(request.description, None);
+    assert_eq!(request.delimiter, Some(b'\t'));
+    assert_eq!(request.has_headers, Some(false));
+    assert_eq!(request.user_columns.email_column.as_deref(), Some("Column 3"));
+
+    assert!(request.set("delimiter", ";;".into()).is_err());
+    assert!(request.set("hasHeaders", "yes".into()).is_err());
+    assert!(request.set("sheet", "2".into()).is_err());
+}
@@ -1,14 +1,74 @@
+use std::convert::Infallible;
+
 use axum::{
-    extract::State,
+    extract::{Path, State},
     http::StatusCode,
-    response::{IntoResponse, Response},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     Json,
 };
+use futures::{stream, StreamExt};
+use uuid::Uuid;
+
+use crate::{
+    app::{
+        api::api_response::{ApiResponseBuilder, ApiResponseData},
+        errors::AppError,
+        jobs,
+    },
+    services::storage::types::JobStatus,
+    state::AppState,
+};
 
-use crate::{app::errors::AppError, state::AppState};
+use super::responses::JobDetailsBuilder;
 
 pub async fn list_jobs(State(state): State<AppState>) -> Result<Response, AppError> {
     let sql = &state.storage.db;
     let jobs = sql.fetch_jobs().await?;
     Ok((StatusCode::OK, Json(jobs)).into_response())
 }
+
+pub async fn fetch_job(State(state): State<AppState>, Path(id): Path<String>) -> Result<Response, AppError> {
+    let sql = &state.storage.db;
+
+    let Some(job) = sql.fetch_job(&id).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
+    let items = sql.fetch_job_items(Uuid::parse_str(&id)?).await?;
+
+    let details = JobDetailsBuilder::default().job(job).items(items).build()?;
+    let res = ApiResponseBuilder::default()
+        .status_code(StatusCode::OK)
+        .data(ApiResponseData::Data(details))
+        .build()?
+        .into_response();
+    Ok(res)
+}
+
+pub async fn stream_job_events(State(state): State<AppState>, Path(id): Path<String>) -> Result<Response, AppError> {
+    let sql = &state.storage.db;
+    let job_id = Uuid::parse_str(&id)?;
+
+    // subscribe before reading the snapshot so no transition falls between the two
+    let receiver = state.events.subscribe();
+
+    let Some(job) = sql.fetch_job(&id).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
+    let finished = job.status != JobStatus::Pending;
+    let snapshot = Event::default().event("snapshot").json_data(&job)?;
+    let snapshot = stream::once(async { Ok::<_, Infallible>(snapshot) });
+
+    let events = match finished {
+        true => snapshot.boxed(),
+        false => snapshot
+            .chain(jobs::job_event_stream(receiver, move |e| e.job_id == job_id, true))
+            .boxed(),
+    };
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()).into_response())
+}
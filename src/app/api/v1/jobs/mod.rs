@@ -1,4 +1,7 @@
 mod controllers;
+mod responses;
+#[cfg(test)]
+mod tests;
 
 use axum::{routing, Router};
 
@@ -7,5 +10,7 @@ use crate::state::AppState;
 pub fn routes(state: AppState) -> Router<()> {
     Router::new()
         .route("/", routing::get(controllers::list_jobs))
+        .route("/:id", routing::get(controllers::fetch_job))
+        .route("/:id/events", routing::get(controllers::stream_job_events))
         .with_state(state)
 }
@@ -0,0 +1,11 @@
+use derive_builder::Builder;
+use serde::Serialize;
+
+use crate::services::storage::entities::{Job, JobItems};
+
+#[derive(Clone, Debug, Serialize, Builder)]
+pub struct JobDetails {
+    #[serde(flatten)]
+    job: Job,
+    items: JobItems,
+}
@@ -0,0 +1,90 @@
+use axum::{body, http::StatusCode, response::IntoResponse};
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    app::errors::AppError,
+    services::storage::{
+        entities::{Job, JobItem},
+        errors::JobConflict,
+        types::{JobItemStatus, JobStatus, JobType},
+    },
+};
+
+use super::responses::JobDetailsBuilder;
+
+#[test]
+pub fn test_job_details_report_progress_and_items() {
+    let at = Utc.with_ymd_and_hms(2024, 5, 6, 12, 0, 0).unwrap();
+    let (job_id, user_id) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let job = Job {
+        id: job_id,
+        created_at: at,
+        updated_at: at,
+        user_id,
+        status: JobStatus::Error,
+        job_type: JobType::ExportData,
+        metadata: json!({}),
+        started_at: Some(at),
+        finished_at: Some(at),
+        total_items: Some(2),
+        processed_items: 2,
+        last_error: Some("handle is already taken".into()),
+    };
+    let items = [
+        (
+            "ada@example.com",
+            JobItemStatus::Success,
+            Some("created ada@developforgood.org"),
+        ),
+        (
+            "ada@example.com",
+            JobItemStatus::Failure,
+            Some("handle is already taken"),
+        ),
+    ]
+    .into_iter()
+    .map(|(item, status, reason)| JobItem {
+        id: Uuid::new_v4(),
+        created_at: at,
+        updated_at: at,
+        job_id,
+        item: item.into(),
+        status,
+        reason: reason.map(str::to_owned),
+    })
+    .collect::<Vec<JobItem>>();
+
+    let details = JobDetailsBuilder::default().job(job).items(items).build().unwrap();
+    let details = serde_json::to_value(&details).unwrap();
+
+    assert_eq!(details["id"], json!(job_id));
+    assert_eq!(details["totalItems"], 2);
+    assert_eq!(details["processedItems"], 2);
+    assert_eq!(details["lastError"], "handle is already taken");
+    assert_eq!(details["items"][0]["status"], "Success");
+    assert_eq!(details["items"][1]["status"], "Failure");
+    assert_eq!(details["items"][1]["reason"], "handle is already taken");
+}
+
+#[tokio::test]
+pub async fn test_job_conflict_points_at_the_pending_job() {
+    let job_id = Uuid::new_v4();
+    let err = anyhow::Error::from(JobConflict {
+        job_id,
+        job_type: JobType::ImportData,
+    })
+    .context("starting import");
+
+    let res = AppError::from(err).into_response();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    let bytes = body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let body = serde_json::from_slice::<Value>(&bytes).unwrap();
+    assert_eq!(
+        body,
+        json!({"error": "a ImportData job is already running for this view", "jobId": job_id})
+    );
+}
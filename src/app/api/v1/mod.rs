@@ -23,3 +23,7 @@ pub fn routes(state: AppState) -> Router<()> {
         .nest("/users", user_routes)
         .nest("/jobs", job_routes)
 }
+
+pub fn webhook_routes(state: AppState) -> Router<()> {
+    airtable::webhook_routes(state)
+}
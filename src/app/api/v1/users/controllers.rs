@@ -15,14 +15,22 @@ use tokio::task;
 use uuid::Uuid;
 
 use crate::{
-    app::errors::AppError,
+    app::{
+        api::api_response::{ApiResponse, ApiResponseBuilder, ApiResponseData},
+        errors::AppError,
+        jobs::{run_on_worker, JobTracker},
+    },
     services::{
         auth::userdata::UserData,
         storage::{
             dto::{CreateJobWithDatasourceBuilder, CreateUserBuilder},
-            entities::{ExportedUser, Job},
-            types::{JobStatus, JobType},
+            entities::ExportedUser,
+            types::{
+                AirtableDatasourceViewMetadata, FileUploadDatasourceViewMetadata, JobStatus, JobType,
+                SupportedDatasource,
+            },
         },
+        uploads::UserColumns,
     },
     state::AppState,
 };
@@ -32,6 +40,8 @@ use super::{
     tasks,
 };
 
+// Calling this again after a partially failed undo resumes it, since only the accounts that could not be deleted
+// remain in the export.
 pub async fn undo_export_job(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -40,24 +50,75 @@ pub async fn undo_export_job(
     let db = &state.storage.db;
     let UserData::Auth0(user_info) = user_info;
 
+    let export_job_uuid = Uuid::parse_str(&id)?;
+
+    let Some(export_job) = db.fetch_job(&id).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
+    if export_job.job_type != JobType::ExportData {
+        return Ok(ApiResponse::error(StatusCode::BAD_REQUEST, "only export jobs can be undone").into_response());
+    }
+
+    let Some(view_uuid) = db.fetch_job_datasource_view_id(export_job_uuid).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
+
     let users_to_delete = db
-        .fetch_exported_users_by_job(Uuid::parse_str(&id)?)
+        .fetch_exported_users_by_job(export_job_uuid)
         .await?
         .into_iter()
         .map(|u| u.generated_email)
         .collect::<Vec<String>>();
 
-    let job_uuid = Uuid::parse_str(&id)?;
-    let admin_email = user_info.email;
+    if users_to_delete.is_empty() {
+        return Ok(
+            ApiResponse::error(StatusCode::BAD_REQUEST, "there are no exported users left to delete").into_response(),
+        );
+    }
+
+    let dto = CreateUserBuilder::default()
+        .email(user_info.email.clone())
+        .first_name(user_info.nickname)
+        .last_name("")
+        .image_uri(user_info.picture)
+        .build()?;
 
+    let user_id = db.create_or_fetch_user(dto).await?;
+
+    let dto = CreateJobWithDatasourceBuilder::default()
+        .status(JobStatus::Pending)
+        .job_type(JobType::UndoExport)
+        .user_id(Uuid::parse_str(&user_id)?)
+        .metadata(serde_json::json!({"datasource_view_id": view_uuid, "export_job_id": export_job_uuid}))
+        .datasource_view_id(view_uuid)
+        .build()?;
+
+    // fails with a conflict while another undo for this view is pending
+    let (job_id, _) = db
+        .create_job_with_datasource(dto, state.settings.stale_job_after.as_secs() as i64)
+        .await?;
+    let job_uuid = Uuid::parse_str(&job_id)?;
+
+    let admin_email = user_info.email;
     let state = state.clone();
-    task::spawn(async move {
-        let _ = tasks::delete_workspace_users(state, users_to_delete, admin_email, job_uuid)
-            .await
-            .unwrap();
-    });
 
-    Ok((StatusCode::OK).into_response())
+    let tracker = JobTracker::new(state.clone(), job_uuid, Some(view_uuid));
+    let heartbeat = tracker.heartbeat();
+    task::spawn(run_on_worker(state.workers.clone(), async move {
+        let _heartbeat = heartbeat;
+        if let Err(e) = tasks::delete_workspace_users(state, users_to_delete, admin_email, tracker.clone()).await {
+            let _ = tracker.fail(&format!("{e:#}")).await;
+        }
+    }));
+
+    let res = ApiResponseBuilder::default()
+        .status_code(StatusCode::ACCEPTED)
+        .data(ApiResponseData::Data(serde_json::json!({"jobId": job_uuid})))
+        .build()?
+        .into_response();
+
+    Ok(res)
 }
 
 pub async fn export_users_to_workspace(
@@ -71,18 +132,6 @@ pub async fn export_users_to_workspace(
 
     let view_uuid = Uuid::parse_str(&id)?;
 
-    let current_view_jobs = db.fetch_datasource_view_jobs(view_uuid).await?;
-
-    // only one job can be started at a time
-    if !current_view_jobs
-        .iter()
-        .filter(|j| j.status == JobStatus::Pending)
-        .collect::<Vec<&Job>>()
-        .is_empty()
-    {
-        return Ok((StatusCode::BAD_REQUEST).into_response());
-    };
-
     let already_exported_users = db
         .fetch_exported_users()
         .await?
@@ -92,24 +141,87 @@ pub async fn export_users_to_workspace(
             last_name: u.last_name.to_owned(),
             email: u.personal_email.to_owned(),
             generated_email: Some(u.generated_email.to_owned()),
+            record_id: None,
         })
         .collect::<Vec<ExportUser>>();
 
-    let users_to_export = export_data
-        .users
-        .iter()
-        .filter(|user| !already_exported_users.contains(user))
-        .cloned()
-        .collect::<Vec<ExportUser>>();
+    let Some(data) = db.fetch_datasource_view(view_uuid).await? else {
+        return Ok((StatusCode::NOT_FOUND).into_response());
+    };
 
-    if let ExportConflictPolicy::Reject = export_data.export_conflict_policy {
-        if users_to_export.len() != export_data.users.len() {
-            return Ok((StatusCode::BAD_REQUEST).into_response());
+    // the columns records are read as users from, by field name
+    let (airtable, user_columns) = match data.datasource {
+        SupportedDatasource::Airtable => {
+            let metadata = serde_json::from_value::<AirtableDatasourceViewMetadata>(data.metadata.clone())?;
+            let field_name = |column: &Option<String>| Some(metadata.field_name(column.as_deref()?).to_owned());
+            let user_columns = match metadata.is_user_table {
+                true => UserColumns {
+                    first_name_column: field_name(&metadata.first_name_column),
+                    last_name_column: field_name(&metadata.last_name_column),
+                    email_column: field_name(&metadata.email_column),
+                },
+                false => UserColumns::default(),
+            };
+            (Some(metadata), user_columns)
+        }
+        SupportedDatasource::FileUpload => {
+            let metadata = serde_json::from_value::<FileUploadDatasourceViewMetadata>(data.metadata.clone())?;
+            (None, metadata.user_columns)
+        }
+        SupportedDatasource::GoogleWorkspaceAdminDirectory => {
+            return Ok(ApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "users can only be exported from airtable and uploaded file views",
+            )
+            .into_response());
         }
     };
 
-    let Some(data) = db.fetch_datasource_view(Uuid::parse_str(&id)?).await? else {
-        return Ok((StatusCode::NOT_FOUND).into_response());
+    let users = match export_data.from_records {
+        None => export_data.users,
+        Some(selection) => {
+            let UserColumns {
+                first_name_column: Some(first_name_column),
+                last_name_column: Some(last_name_column),
+                email_column: Some(email_column),
+            } = user_columns
+            else {
+                return Ok(
+                    ApiResponse::error(StatusCode::BAD_REQUEST, "this view has no user column mapping").into_response(),
+                );
+            };
+
+            let Some(records) = state.storage.fetch_view_records(view_uuid).await? else {
+                return Ok(
+                    ApiResponse::error(StatusCode::CONFLICT, "this view has not been imported yet").into_response(),
+                );
+            };
+
+            tasks::users_from_records(
+                records,
+                &first_name_column,
+                &last_name_column,
+                &email_column,
+                &selection,
+            )
+        }
+    };
+
+    let plan = tasks::plan_export(users, &already_exported_users, &export_data.email_policy);
+
+    if export_data.dry_run {
+        let res = ApiResponseBuilder::default()
+            .status_code(StatusCode::OK)
+            .data(ApiResponseData::Data(plan))
+            .build()?
+            .into_response();
+        return Ok(res);
+    }
+
+    if let ExportConflictPolicy::Reject = export_data.export_conflict_policy {
+        if !plan.skipped.is_empty() {
+            return Ok((StatusCode::BAD_REQUEST).into_response());
+        }
     };
 
     let dto = CreateUserBuilder::default()
@@ -129,22 +241,32 @@ pub async fn export_users_to_workspace(
         .datasource_view_id(data.id)
         .build()?;
 
-    let (job_id, _) = db.create_job_with_datasource(dto).await?;
+    // fails with a conflict while another export for this view is pending
+    let (job_id, _) = db
+        .create_job_with_datasource(dto, state.settings.stale_job_after.as_secs() as i64)
+        .await?;
     let job_uuid = Uuid::parse_str(&job_id)?;
 
     let state = state.clone();
 
-    task::spawn(async move {
-        let _ = tasks::create_workspace_users(
+    let tracker = JobTracker::new(state.clone(), job_uuid, Some(data.id));
+    let heartbeat = tracker.heartbeat();
+    task::spawn(run_on_worker(state.workers.clone(), async move {
+        let _heartbeat = heartbeat;
+        if let Err(e) = tasks::create_workspace_users(
             state,
-            users_to_export,
-            export_data.email_policy,
+            plan,
             export_data.password_policy,
             user_info.email,
-            job_uuid,
+            data.datasource,
+            airtable,
+            tracker.clone(),
         )
-        .await;
-    });
+        .await
+        {
+            let _ = tracker.fail(&format!("{e:#}")).await;
+        }
+    }));
 
     Ok((StatusCode::OK, "started job").into_response())
 }
@@ -164,7 +286,8 @@ pub async fn download_exported_users_as_csv(
     Extension(user_info): Extension<UserData>,
     Json(payload): Json<DownloadUsersRequest>,
 ) -> Result<Response, AppError> {
-    let (db, mail) = (&state.storage.db, &state.mail);
+    let db = &state.storage.db;
+    let view_uuid = Uuid::parse_str(&id)?;
 
     log::info!("WE ARE HERE");
     dbg!(&payload);
@@ -184,16 +307,34 @@ pub async fn download_exported_users_as_csv(
         .job_type(JobType::ExportData)
         .user_id(Uuid::parse_str(&user_id)?)
         .metadata(serde_json::json!({}))
-        .datasource_view_id(Uuid::parse_str(&id)?)
+        .datasource_view_id(view_uuid)
         .build()?;
 
-    let (job_id, _) = db.create_job_with_datasource(dto).await?;
-    // let job_uuid = Uuid::parse_str(&job_id)?;
-    //
-    // let state = state.clone();
-    //
+    let (job_id, _) = db
+        .create_job_with_datasource(dto, state.settings.stale_job_after.as_secs() as i64)
+        .await?;
+    let tracker = JobTracker::new(state.clone(), Uuid::parse_str(&job_id)?, Some(view_uuid));
+
+    // the job has to be settled either way, a pending job holds the view's export lock
+    match send_exported_users_csv(&state, view_uuid, payload).await {
+        Ok(_) => tracker.complete().await?,
+        Err(e) => {
+            tracker.fail(&format!("{e:#}")).await?;
+            return Err(e.into());
+        }
+    };
 
-    let all_exported_users = db.fetch_exported_users_by_view(Uuid::parse_str(&id)?).await?;
+    Ok((StatusCode::OK).into_response())
+}
+
+async fn send_exported_users_csv(
+    state: &AppState,
+    view_uuid: Uuid,
+    payload: DownloadUsersRequest,
+) -> anyhow::Result<()> {
+    let (db, mail) = (&state.storage.db, &state.mail);
+
+    let all_exported_users = db.fetch_exported_users_by_view(view_uuid).await?;
 
     let csv_users = payload
         .user_data
@@ -274,5 +415,5 @@ pub async fn download_exported_users_as_csv(
     //     let _ = tasks::create_and_send_csv(state, payload.send_to, payload.user_data, payload.columns, job_uuid).await;
     // });
 
-    Ok((StatusCode::OK).into_response())
+    Ok(())
 }
@@ -1,15 +1,24 @@
 use axum::{routing, Router};
 
-use crate::state::AppState;
+use crate::{app::middleware, state::AppState};
 
 mod controllers;
 mod requests;
 mod responses;
-mod tasks;
+pub(super) mod tasks;
+
+#[cfg(test)]
+mod tests;
 
 pub fn routes(state: AppState) -> Router<()> {
     Router::new()
-        .route("/:id/export", routing::post(controllers::export_users_to_workspace))
+        .route(
+            "/:id/export",
+            routing::post(controllers::export_users_to_workspace).route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                middleware::idempotency,
+            )),
+        )
         .route("/jobs/:id/undo", routing::delete(controllers::undo_export_job))
         .route(
             "/download/:id",
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -23,6 +23,8 @@ pub struct ExportUser {
     pub last_name: String,
     pub email: String,
     pub generated_email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record_id: Option<String>,
 }
 
 impl PartialEq for ExportUser {
@@ -48,13 +50,28 @@ pub enum ExportConflictPolicy {
     Reject,
 }
 
+// Selects which of the view's cached records to export. Records must match every given field value, and be one of the
+// given record ids if any are listed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRecordSelection {
+    pub record_ids: Option<Vec<String>>,
+    #[serde(default)]
+    pub filter: Map<String, Value>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExportUsersRequest {
+    #[serde(default)]
     pub users: Vec<ExportUser>,
+    // when set, the users are resolved from the view's cached records instead of `users`
+    pub from_records: Option<ExportRecordSelection>,
     pub email_policy: EmailPolicy,
     pub password_policy: PasswordPolicy,
     pub export_conflict_policy: ExportConflictPolicy,
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
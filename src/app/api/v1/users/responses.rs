@@ -0,0 +1,21 @@
+use serde::Serialize;
+
+use super::requests::ExportUser;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportProblem {
+    pub user: ExportUser,
+    pub reason: String,
+}
+
+// The outcome of resolving an export request: the users that will be created with their handles, and the ones that
+// will not be, with the reason why.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportPlan {
+    pub users: Vec<ExportUser>,
+    pub skipped: Vec<ExportUser>,
+    pub problems: Vec<ExportProblem>,
+    pub collisions: Vec<ExportProblem>,
+}
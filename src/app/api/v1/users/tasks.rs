@@ -1,23 +1,176 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::Utc;
 use sendgrid::Mail;
-use serde_json::Value;
-use std::time::Duration;
+use serde_json::{Map, Value};
+use std::{collections::HashSet, time::Duration};
 
 use rand::{distributions::Alphanumeric, Rng};
 use uuid::Uuid;
 
 use crate::{
+    app::jobs::JobTracker,
     services::{
+        airtable::record::{Record, UpdateRecord},
         storage::{
             dto::{CreateExportedUser, CreateExportedUserBuilder},
-            types::SupportedDatasource,
+            types::{AirtableDatasourceViewMetadata, AirtableWriteBack, JobItemStatus, SupportedDatasource},
+        },
+        workspace::{
+            errors::UserNotFound,
+            users::{CreateWorkspaceUserBuilder, NameBuilder},
         },
-        workspace::users::{CreateWorkspaceUserBuilder, NameBuilder},
     },
     state::AppState,
 };
 
-use super::requests::{EmailPolicy, ExportUser, ExportUsersRequest, PasswordPolicy};
+use super::{
+    requests::{EmailPolicy, ExportRecordSelection, ExportUser, ExportUsersRequest, PasswordPolicy},
+    responses::{ExportPlan, ExportProblem},
+};
+
+const WORKSPACE_DOMAIN: &str = "developforgood.org";
+
+// Resolves which users an export would create and the handles they would get, without side effects. Handles are
+// deterministic so a dry run shows exactly what the export will do.
+pub fn plan_export(users: Vec<ExportUser>, already_exported: &[ExportUser], email_policy: &EmailPolicy) -> ExportPlan {
+    let mut plan = ExportPlan::default();
+
+    let mut taken = already_exported
+        .iter()
+        .filter_map(|u| u.generated_email.clone())
+        .collect::<HashSet<String>>();
+    let mut seen = HashSet::new();
+
+    for mut user in users {
+        if already_exported.contains(&user) {
+            plan.skipped.push(user);
+            continue;
+        }
+
+        if let Some(reason) = validate_export_user(&user, email_policy) {
+            plan.problems.push(ExportProblem { user, reason });
+            continue;
+        }
+
+        if !seen.insert(user.email.trim().to_lowercase()) {
+            plan.problems.push(ExportProblem {
+                user,
+                reason: "listed more than once".into(),
+            });
+            continue;
+        }
+
+        let base = if email_policy.use_both_first_and_last_names {
+            format!(
+                "{}{}{}",
+                handle_part(&user.first_name),
+                email_policy.separator,
+                handle_part(&user.last_name)
+            )
+        } else {
+            handle_part(&user.first_name)
+        };
+
+        let handle = if email_policy.add_unique_numeric_suffix {
+            let mut n = 1;
+            while taken.contains(&format!("{base}{n}@{WORKSPACE_DOMAIN}")) {
+                n += 1;
+            }
+            format!("{base}{n}@{WORKSPACE_DOMAIN}")
+        } else {
+            format!("{base}@{WORKSPACE_DOMAIN}")
+        };
+
+        user.generated_email = Some(handle.clone());
+
+        if !taken.insert(handle) {
+            plan.collisions.push(ExportProblem {
+                user,
+                reason: "handle is already taken".into(),
+            });
+            continue;
+        }
+
+        plan.users.push(user);
+    }
+
+    plan
+}
+
+// Builds export users from a view's cached records using the view's column mapping.
+pub fn users_from_records(
+    records: Vec<Record<Value>>,
+    first_name_column: &str,
+    last_name_column: &str,
+    email_column: &str,
+    selection: &ExportRecordSelection,
+) -> Vec<ExportUser> {
+    records
+        .into_iter()
+        .filter(|r| selection.record_ids.as_ref().is_none_or(|ids| ids.contains(&r.id)))
+        .filter(|r| {
+            selection
+                .filter
+                .iter()
+                .all(|(field, expected)| match r.fields.get(field) {
+                    Some(Value::Array(values)) => values.contains(expected),
+                    Some(value) => value == expected,
+                    None => expected.is_null(),
+                })
+        })
+        .map(|r| ExportUser {
+            first_name: cell_text(r.fields.get(first_name_column)),
+            last_name: cell_text(r.fields.get(last_name_column)),
+            email: cell_text(r.fields.get(email_column)),
+            generated_email: None,
+            record_id: Some(r.id),
+        })
+        .collect()
+}
+
+// Lookup and rollup fields come back as arrays, so their first value is used.
+fn cell_text(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(s)) => s.trim().to_owned(),
+        Some(Value::Number(n)) => n.to_string(),
+        Some(Value::Array(values)) => cell_text(values.first()),
+        _ => String::new(),
+    }
+}
+
+fn handle_part(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect()
+}
+
+fn validate_export_user(user: &ExportUser, email_policy: &EmailPolicy) -> Option<String> {
+    if handle_part(&user.first_name).is_empty() {
+        return Some("missing first name".into());
+    }
+    if email_policy.use_both_first_and_last_names && handle_part(&user.last_name).is_empty() {
+        return Some("missing last name".into());
+    }
+    if !is_valid_email(&user.email) {
+        return Some(format!("invalid email address {:?}", user.email));
+    }
+    None
+}
+
+fn is_valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.trim().split_once('@') else {
+        return false;
+    };
+
+    !local.is_empty()
+        && !local.contains(char::is_whitespace)
+        && !domain.contains(['@', ' '])
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+}
 
 pub async fn create_and_send_csv(
     state: AppState,
@@ -29,60 +182,88 @@ pub async fn create_and_send_csv(
     Ok(())
 }
 
+// Deleted and already missing accounts are dropped from the exported users, so running the undo again only retries
+// the accounts that failed.
 pub async fn delete_workspace_users(
     state: AppState,
     users_to_delete: Vec<String>,
     admin_email: String,
-    job_uuid: Uuid,
+    tracker: JobTracker,
 ) -> Result<()> {
     let (db, workspace) = (&state.storage.db, &state.workspace_client);
 
+    tracker.start(Some(users_to_delete.len() as i32)).await?;
+
+    let mut last_error: Option<String> = None;
+
     for user in users_to_delete {
         match workspace.delete_user(&admin_email, &user).await {
             Ok(_) => {
                 log::info!("successfully deleted user");
-                let _ = db.delete_exported_user_by_email(&user).await;
+                db.delete_exported_user_by_email(&user).await?;
+                tracker.record_item(&user, JobItemStatus::Success, None).await?;
+            }
+            Err(e) if e.is::<UserNotFound>() => {
+                db.delete_exported_user_by_email(&user).await?;
+                tracker
+                    .record_item(&user, JobItemStatus::Skipped, Some(e.to_string()))
+                    .await?;
             }
-            Err(_) => {
-                let _ = db.mark_job_errored(job_uuid).await;
-                break;
+            Err(e) => {
+                let reason = format!("{e:#}");
+                tracker
+                    .record_item(&user, JobItemStatus::Failure, Some(reason.clone()))
+                    .await?;
+                last_error = Some(reason);
             }
         };
     }
 
-    let _ = db.mark_job_complete(job_uuid).await;
+    match last_error {
+        Some(e) => tracker.fail(&e).await?,
+        None => tracker.complete().await?,
+    };
 
     Ok(())
 }
 
 pub async fn create_workspace_users(
     state: AppState,
-    users_to_export: Vec<ExportUser>,
-    email_policy: EmailPolicy,
+    plan: ExportPlan,
     password_policy: PasswordPolicy,
     admin_email: String,
-    job_uuid: Uuid,
+    exported_from: SupportedDatasource,
+    // only airtable views can have generated emails written back to them
+    airtable: Option<AirtableDatasourceViewMetadata>,
+    tracker: JobTracker,
 ) -> Result<()> {
     let (db, workspace, mail) = (&state.storage.db, &state.workspace_client, &state.mail);
 
+    let total = plan.users.len() + plan.problems.len() + plan.collisions.len();
+    tracker.start(Some(total as i32)).await?;
+
     let mut created_users: Vec<ExportUser> = vec![];
+    let mut last_error: Option<String> = None;
 
-    for (i, mut user) in users_to_export.into_iter().enumerate() {
+    for problem in plan.problems.into_iter().chain(plan.collisions) {
+        tracker
+            .record_item(
+                &problem.user.email,
+                JobItemStatus::Failure,
+                Some(problem.reason.clone()),
+            )
+            .await?;
+        last_error = Some(problem.reason);
+    }
+
+    for (i, user) in plan.users.into_iter().enumerate() {
         if i % 8 == 0 {
             tokio::time::sleep(Duration::from_secs(3)).await;
         };
 
-        let num = rand::thread_rng().gen_range(10..100);
-
-        let new_email = format!(
-            "{}{}{}{}@developforgood.org",
-            user.first_name.trim().to_lowercase(),
-            email_policy.separator,
-            user.last_name.trim().to_lowercase(),
-            num
-        );
-
-        user.generated_email = Some(new_email.clone());
+        let Some(new_email) = user.generated_email.clone() else {
+            continue;
+        };
 
         let password = rand::thread_rng()
             .sample_iter(&Alphanumeric)
@@ -119,16 +300,26 @@ pub async fn create_workspace_users(
                              You will need to change it at your next login",
                             )),
                     )
-                    .await?;
+                    .await;
 
-                dbg!(&res.text().await?);
-                created_users.push(user);
+                // the account exists at this point, so a failed welcome email is reported on the item rather than
+                // failing the export
+                let reason = match res {
+                    Ok(_) => format!("created {new_email}"),
+                    Err(e) => format!("created {new_email}, but the welcome email could not be sent: {e}"),
+                };
 
-                continue;
+                tracker
+                    .record_item(&user.email, JobItemStatus::Success, Some(reason))
+                    .await?;
+                created_users.push(user);
             }
-            Err(_) => {
-                db.mark_job_errored(job_uuid).await?;
-                break;
+            Err(e) => {
+                let reason = format!("{e:#}");
+                tracker
+                    .record_item(&user.email, JobItemStatus::Failure, Some(reason.clone()))
+                    .await?;
+                last_error = Some(reason);
             }
         };
     }
@@ -141,8 +332,9 @@ pub async fn create_workspace_users(
                 .last_name(u.last_name.to_owned())
                 .personal_email(u.email.to_owned())
                 .generated_email(u.generated_email.clone()?)
-                .exported_from(SupportedDatasource::Airtable)
-                .job_id(job_uuid)
+                .exported_from(exported_from)
+                .job_id(tracker.job_id)
+                .source_record_id(u.record_id.clone())
                 .build()
             else {
                 return None;
@@ -152,7 +344,56 @@ pub async fn create_workspace_users(
         .collect::<Vec<CreateExportedUser>>();
 
     db.save_exported_users(users_to_export).await?;
-    db.mark_job_complete(job_uuid).await?;
+
+    if let Some((metadata, write_back)) = airtable.as_ref().and_then(|m| Some((m, m.write_back.as_ref()?))) {
+        // the accounts exist either way, so a failed write back is reported on the job rather than aborting it
+        if let Err(e) = write_back_generated_emails(&state, metadata, write_back, &created_users).await {
+            last_error = Some(format!("{e:#}"));
+        }
+    }
+
+    match last_error {
+        Some(e) => tracker.fail(&e).await?,
+        None => tracker.complete().await?,
+    };
+
+    Ok(())
+}
+
+// Patches each created user's generated email, and the export time if configured, onto the record it came from.
+async fn write_back_generated_emails(
+    state: &AppState,
+    metadata: &AirtableDatasourceViewMetadata,
+    write_back: &AirtableWriteBack,
+    users: &[ExportUser],
+) -> Result<()> {
+    let exported_at = Utc::now().to_rfc3339();
+
+    let updates = users
+        .iter()
+        .filter_map(|u| {
+            let mut fields = Map::new();
+            fields.insert(write_back.email_column.clone(), u.generated_email.clone()?.into());
+            if let Some(ref column) = write_back.exported_at_column {
+                fields.insert(column.clone(), exported_at.clone().into());
+            }
+
+            Some(UpdateRecord {
+                id: u.record_id.clone()?,
+                fields,
+            })
+        })
+        .collect::<Vec<UpdateRecord<Map<String, Value>>>>();
+
+    if updates.is_empty() {
+        return Ok(());
+    }
+
+    state
+        .airtable
+        .update_records(&metadata.base, &metadata.table, &updates)
+        .await
+        .context("write generated emails back to airtable")?;
 
     Ok(())
 }
@@ -0,0 +1,113 @@
+use serde_json::{json, Value};
+
+use crate::services::airtable::record::Record;
+
+use super::{
+    requests::{EmailPolicy, ExportRecordSelection, ExportUser},
+    tasks::{plan_export, users_from_records},
+};
+
+fn user(first_name: &str, last_name: &str, email: &str) -> ExportUser {
+    ExportUser {
+        first_name: first_name.into(),
+        last_name: last_name.into(),
+        email: email.into(),
+        generated_email: None,
+        record_id: None,
+    }
+}
+
+fn policy(add_unique_numeric_suffix: bool) -> EmailPolicy {
+    EmailPolicy {
+        use_both_first_and_last_names: true,
+        add_unique_numeric_suffix,
+        separator: ".".into(),
+    }
+}
+
+#[test]
+pub fn test_plan_export_generates_unique_handles() {
+    let already_exported = vec![ExportUser {
+        generated_email: Some("jane.doe1@developforgood.org".into()),
+        ..user("Jane", "Doe", "jane@example.com")
+    }];
+
+    let plan = plan_export(
+        vec![
+            user("Jane", "Doe", "jane@example.com"),
+            user(" Jane ", "Doe", "jane.doe@example.org"),
+            user("Jane", "Doe", "jdoe@example.net"),
+        ],
+        &already_exported,
+        &policy(true),
+    );
+
+    assert_eq!(plan.skipped.len(), 1);
+    let handles = plan
+        .users
+        .iter()
+        .filter_map(|u| u.generated_email.as_deref())
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        handles,
+        vec!["jane.doe2@developforgood.org", "jane.doe3@developforgood.org"]
+    );
+}
+
+#[test]
+pub fn test_plan_export_reports_problems_and_collisions() {
+    let plan = plan_export(
+        vec![
+            user("", "Doe", "someone@example.com"),
+            user("John", "Smith", "not-an-email"),
+            user("John", "Doe", "john@example.com"),
+            user("John", "Doe", "john@example.com"),
+            user("John", "Doe", "john.doe@example.com"),
+        ],
+        &[],
+        &policy(false),
+    );
+
+    assert_eq!(plan.users.len(), 1);
+    assert_eq!(plan.problems.len(), 3);
+    assert_eq!(plan.collisions.len(), 1);
+    assert_eq!(
+        plan.collisions[0].user.generated_email.as_deref(),
+        Some("john.doe@developforgood.org")
+    );
+}
+
+#[test]
+pub fn test_users_from_records() {
+    let record = |id: &str, fields: Value| Record {
+        id: id.into(),
+        fields,
+        created_time: "2024-05-01T00:00:00.000Z".into(),
+    };
+
+    let records = vec![
+        record(
+            "rec1",
+            json!({"First": "Jane", "Last": ["Doe"], "Email": "jane@example.com", "Cohort": "Spring"}),
+        ),
+        record(
+            "rec2",
+            json!({"First": "John", "Last": "Doe", "Email": "john@example.com", "Cohort": "Fall"}),
+        ),
+        record("rec3", json!({"First": "Ann", "Cohort": "Spring"})),
+    ];
+
+    let selection = serde_json::from_value::<ExportRecordSelection>(json!({"filter": {"Cohort": "Spring"}})).unwrap();
+    let users = users_from_records(records.clone(), "First", "Last", "Email", &selection);
+
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[0].last_name, "Doe");
+    assert_eq!(users[0].record_id.as_deref(), Some("rec1"));
+    assert_eq!(users[1].email, "");
+
+    let selection = serde_json::from_value::<ExportRecordSelection>(json!({"recordIds": ["rec2"]})).unwrap();
+    let users = users_from_records(records, "First", "Last", "Email", &selection);
+
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].first_name, "John");
+}
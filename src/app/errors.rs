@@ -2,11 +2,35 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::services::storage::errors::JobConflict;
+
+use super::api::api_response::{ApiResponse, ApiResponseData};
 
 pub struct AppError(anyhow::Error);
 
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct JobConflictResponse {
+    error: String,
+    job_id: Uuid,
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let Some(conflict) = self.0.downcast_ref::<JobConflict>() {
+            return ApiResponse {
+                status_code: StatusCode::CONFLICT,
+                data: ApiResponseData::Data(JobConflictResponse {
+                    error: conflict.to_string(),
+                    job_id: conflict.job_id,
+                }),
+            }
+            .into_response();
+        }
+
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Something went wrong: {}", self.0),
@@ -1,11 +1,75 @@
-use std::time::Duration;
+mod scheduler;
+mod stream;
+#[cfg(test)]
+mod tests;
+mod tracker;
+mod webhooks;
+mod workspace;
 
-use anyhow::Result;
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use anyhow::{bail, Context, Result};
 use rand::Rng;
 use serde_json::{Map, Value};
+
+use tokio::{
+    sync::Semaphore,
+    task::{self, JoinHandle},
+};
 use uuid::Uuid;
 
-use crate::{services::airtable::ListRecordsOptionsBuilder, state::AppState};
+use crate::{
+    services::{
+        airtable::{
+            record::Record,
+            schema::{CellValue, FieldType, Schema, Table},
+            Airtable, ListRecordsOptionsBuilder,
+        },
+        storage::{
+            dto::CreateJobWithDatasourceBuilder,
+            entities::DatasourceView,
+            types::{
+                AirtableDatasourceViewMetadata, JobStatus, JobType, SupportedDatasource,
+                WorkspaceDatasourceViewMetadata,
+            },
+        },
+    },
+    state::AppState,
+};
+
+pub use scheduler::run_refresh_scheduler;
+pub use stream::job_event_stream;
+pub use tracker::JobTracker;
+pub use webhooks::{register_airtable_webhook, sync_airtable_webhook, unregister_airtable_webhook};
+
+// Waits for a free worker slot before running the job, so at most `max_concurrent_jobs` jobs do work at once.
+pub async fn run_on_worker(workers: Arc<Semaphore>, job: impl Future<Output = ()>) {
+    let Ok(_permit) = workers.acquire_owned().await else {
+        return;
+    };
+    job.await
+}
+
+// Cached airtable data is namespaced by token so switching tokens never serves another token's bases.
+pub fn airtable_cache_key(state: &AppState, key: &str) -> String {
+    format!("airtable:{}:{key}", state.airtable.cache_namespace())
+}
+
+pub async fn cached_schema(state: &AppState, base_id: &str) -> Result<Schema> {
+    let cache = &state.storage.cache;
+    let key = airtable_cache_key(state, &format!("schema:{base_id}"));
+
+    if let Some(schema) = cache.get_json::<Schema>(&key).await? {
+        return Ok(schema);
+    }
+
+    let schema = state.airtable.fetch_schema(base_id).await?;
+    cache
+        .set_json_with_ttl(&key, schema.clone(), state.settings.airtable_cache_ttl)
+        .await?;
+
+    Ok(schema)
+}
 
 // pub struct FetchAirtableDataParams {
 //
@@ -13,29 +77,228 @@ use crate::{services::airtable::ListRecordsOptionsBuilder, state::AppState};
 
 pub async fn fetch_and_cache_airtable_data(
     state: AppState,
-    job_id: Uuid,
+    tracker: &JobTracker,
     new_datasource_view_id: String,
-    base: String,
-    table: String,
-    view: String,
-    fields: Vec<String>,
+    mut metadata: AirtableDatasourceViewMetadata,
     offset: Option<String>,
 ) -> Result<()> {
-    let (airtable, db, cache) = (&state.airtable, &state.storage.db, &state.storage.cache);
+    let db = &state.storage.db;
+    let view_id = Uuid::parse_str(&new_datasource_view_id)?;
+
+    tracker.start(None).await?;
+
+    let schema = cached_schema(&state, &metadata.base).await?;
+    let table = schema
+        .table(&metadata.table)
+        .with_context(|| format!("base {} has no table {}", metadata.base, metadata.table))?;
+
+    let mut records = fetch_airtable_records(&state.airtable, &metadata, table, offset).await?;
+    expand_record_links(&state.airtable, &metadata, &schema, &mut records).await?;
+    let total = records.len() as i32;
+
+    let changes = state
+        .storage
+        .save_view_records(view_id, Some(tracker.job_id), &records)
+        .await?;
+    log::info!("import of {view_id} found {} changed records", changes.len());
+    tracker.progress(total, Some(total)).await?;
+
+    if metadata.refresh_field_names(table) {
+        db.set_datasource_view_metadata(view_id, &serde_json::to_value(&metadata)?)
+            .await?;
+    }
 
-    let Ok(mut opts) = ListRecordsOptionsBuilder::default()
-        .view(view)
-        .fields(fields)
+    Ok(())
+}
+
+// Fetches the view's records by field id and keys them by the fields' current names in the table.
+pub async fn fetch_airtable_records(
+    airtable: &Airtable,
+    metadata: &AirtableDatasourceViewMetadata,
+    table: &Table,
+    offset: Option<String>,
+) -> Result<Vec<Record<Value>>> {
+    let mut opts = ListRecordsOptionsBuilder::default()
+        .view(metadata.view.clone())
+        .fields(metadata.fields.clone())
         .offset(offset)
-        .build()
-    else {
-        db.mark_job_errored(job_id).await?;
-        return Ok(());
+        .filter_by_formula(metadata.filter_formula.clone())
+        .return_fields_by_field_id(true)
+        .build()?;
+
+    let records = airtable
+        .list_all_records::<Map<String, Value>>(&metadata.base, &metadata.table, &mut opts)
+        .await?;
+
+    let records = records
+        .into_iter()
+        .map(|r| Record {
+            id: r.id,
+            created_time: r.created_time,
+            fields: r
+                .fields
+                .into_iter()
+                .map(|(field, value)| match table.field(&field) {
+                    Some(f) => (f.name.clone(), value),
+                    None => (field, value),
+                })
+                .collect::<Map<String, Value>>()
+                .into(),
+        })
+        .collect();
+
+    Ok(records)
+}
+
+// keeps the filter formula of a linked records request well within airtable's url length limit
+const LINKED_RECORDS_PER_REQUEST: usize = 100;
+
+// Replaces linked record ids with the display field of the linked records for each of the view's link expansions.
+// Values that aren't ids of linked records, such as ones already expanded, are left as they are.
+pub async fn expand_record_links(
+    airtable: &Airtable,
+    metadata: &AirtableDatasourceViewMetadata,
+    schema: &Schema,
+    records: &mut [Record<Value>],
+) -> Result<()> {
+    let Some(table) = schema.table(&metadata.table) else {
+        bail!("base {} has no table {}", metadata.base, metadata.table);
     };
 
-    let records = airtable.list_all_records::<Value>(&base, &table, &mut opts).await?;
+    for expansion in &metadata.link_expansions {
+        let Some(field) = table.field(&expansion.field) else {
+            bail!("table {} has no field {}", table.name, expansion.field);
+        };
+        let FieldType::MultipleRecordLinks(ref options) = field.field_type else {
+            bail!("field {} does not link to another table", field.name);
+        };
+        let Some(display_field) = schema
+            .table(&options.linked_table_id)
+            .and_then(|t| t.field(&expansion.display_field))
+        else {
+            bail!(
+                "linked table {} has no field {}",
+                options.linked_table_id,
+                expansion.display_field
+            );
+        };
 
-    cache.set_json(&new_datasource_view_id, records).await?;
+        // only the linked records are fetched, rather than the whole linked table
+        let mut ids = records
+            .iter()
+            .filter_map(|r| r.fields.get(&field.name)?.as_array())
+            .flatten()
+            .filter_map(Value::as_str)
+            .filter(|id| id.starts_with("rec"))
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let mut linked = Vec::new();
+        for batch in ids.chunks(LINKED_RECORDS_PER_REQUEST) {
+            let formula = batch
+                .iter()
+                .map(|id| format!("RECORD_ID()='{id}'"))
+                .collect::<Vec<_>>()
+                .join(",");
+
+            let mut opts = ListRecordsOptionsBuilder::default()
+                .view(None)
+                .fields(vec![display_field.id.clone()])
+                .offset(None)
+                .filter_by_formula(format!("OR({formula})"))
+                .return_fields_by_field_id(true)
+                .build()?;
+
+            let mut page = airtable
+                .list_all_records::<Map<String, Value>>(&metadata.base, &options.linked_table_id, &mut opts)
+                .await
+                .with_context(|| format!("fetch records linked from {}", field.name))?;
+            linked.append(&mut page);
+        }
+
+        let display_values = linked
+            .into_iter()
+            .filter_map(|r| {
+                let value = r.fields.get(&display_field.id)?;
+                let text = CellValue::decode(&display_field.field_type, value)
+                    .ok()
+                    .and_then(|cell| cell.as_text());
+                Some((r.id, text.map_or_else(|| value.clone(), Value::String)))
+            })
+            .collect::<HashMap<String, Value>>();
+
+        for record in records.iter_mut() {
+            let Some(Value::Array(links)) = record.fields.get_mut(&field.name) else {
+                continue;
+            };
+            for link in links.iter_mut() {
+                if let Some(value) = link.as_str().and_then(|id| display_values.get(id)) {
+                    *link = value.clone();
+                }
+            }
+        }
+    }
 
     Ok(())
 }
+
+enum ImportSource {
+    Airtable(AirtableDatasourceViewMetadata),
+    Workspace(WorkspaceDatasourceViewMetadata),
+}
+
+// Creates an import job for the view and runs it in the background, returning the job id and the task handle.
+pub async fn start_import_job(
+    state: AppState,
+    user_id: Uuid,
+    view: &DatasourceView,
+) -> Result<(String, JoinHandle<()>)> {
+    let db = &state.storage.db;
+
+    // read before the job is created so a view with bad metadata doesn't leave a failed job behind
+    let source = match view.datasource {
+        SupportedDatasource::Airtable => ImportSource::Airtable(serde_json::from_value(view.metadata.clone())?),
+        SupportedDatasource::GoogleWorkspaceAdminDirectory => {
+            ImportSource::Workspace(serde_json::from_value(view.metadata.clone())?)
+        }
+        SupportedDatasource::FileUpload => bail!("the rows of an uploaded file are stored when it is uploaded"),
+    };
+
+    let dto = CreateJobWithDatasourceBuilder::default()
+        .status(JobStatus::Pending)
+        .job_type(JobType::ImportData)
+        .user_id(user_id)
+        .metadata(serde_json::json!({"datasource_view_id": &view.id}))
+        .datasource_view_id(view.id)
+        .build()?;
+
+    let (job_id, _) = db
+        .create_job_with_datasource(dto, state.settings.stale_job_after.as_secs() as i64)
+        .await?;
+    let job_uuid = Uuid::parse_str(&job_id)?;
+
+    let view_id = view.id;
+    let state = state.clone();
+
+    // the job stays pending until a worker slot frees up, beating all the while so it isn't taken for abandoned
+    let tracker = JobTracker::new(state.clone(), job_uuid, Some(view_id));
+    let heartbeat = tracker.heartbeat();
+    let handle = task::spawn(run_on_worker(state.workers.clone(), async move {
+        let _heartbeat = heartbeat;
+        let result = match source {
+            ImportSource::Airtable(metadata) => {
+                fetch_and_cache_airtable_data(state, &tracker, view_id.to_string(), metadata, None).await
+            }
+            ImportSource::Workspace(metadata) => {
+                workspace::fetch_and_cache_workspace_users(state, &tracker, view_id, metadata).await
+            }
+        };
+        let _ = match result {
+            Ok(_) => tracker.complete().await,
+            Err(e) => tracker.fail(&format!("{e:#}")).await,
+        };
+    }));
+
+    Ok((job_id, handle))
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    services::storage::{entities::DatasourceView, errors::JobConflict},
+    state::AppState,
+};
+
+use super::{start_import_job, webhooks::refresh_expiring_webhooks};
+
+pub async fn run_refresh_scheduler(state: AppState, tick: Duration) {
+    let mut interval = tokio::time::interval(tick);
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = refresh_expiring_webhooks(&state).await {
+            log::warn!("unable to refresh expiring airtable webhooks: {e:#}");
+        }
+
+        let views = match state.storage.db.fetch_due_datasource_views().await {
+            Ok(views) => views,
+            Err(e) => {
+                log::warn!("unable to fetch datasource views due for refresh: {e:#}");
+                continue;
+            }
+        };
+
+        let now = Utc::now();
+        for view in views {
+            if let Err(e) = enqueue_refresh(&state, &view, now).await {
+                log::warn!("unable to schedule refresh of {}: {e:#}", view.id);
+            }
+        }
+    }
+}
+
+async fn enqueue_refresh(state: &AppState, view: &DatasourceView, now: DateTime<Utc>) -> Result<()> {
+    let db = &state.storage.db;
+
+    let (Some(schedule), Some(due_at)) = (&view.refresh_schedule, view.next_refresh_at) else {
+        return Ok(());
+    };
+
+    let next_refresh_at = match schedule.next_after(now) {
+        Ok(next_refresh_at) => next_refresh_at,
+        Err(e) => {
+            // a schedule with no runs left would otherwise stay due, and fail here, on every tick
+            log::warn!("disabling the refresh schedule of {}: {e:#}", view.id);
+            return db.set_datasource_view_schedule(view.id, None, None).await;
+        }
+    };
+    if !db.claim_scheduled_refresh(view.id, due_at, next_refresh_at).await? {
+        return Ok(());
+    }
+
+    match start_import_job(state.clone(), view.user_id, view).await {
+        Ok((job_id, _)) => log::info!("started scheduled refresh of {} as job {job_id}", view.id),
+        Err(e) if e.is::<JobConflict>() => {
+            log::info!(
+                "skipping scheduled refresh of {}, an import is already running",
+                view.id
+            )
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use std::convert::Infallible;
+
+use axum::response::sse::Event;
+use futures::{stream, Stream};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+use crate::services::events::JobEvent;
+
+// Turns the job event broadcast into a stream of SSE events matching `filter`. When `until_terminal` is set, the
+// stream ends after the first matching complete/errored event.
+pub fn job_event_stream<F>(
+    receiver: Receiver<JobEvent>,
+    filter: F,
+    until_terminal: bool,
+) -> impl Stream<Item = Result<Event, Infallible>>
+where
+    F: Fn(&JobEvent) -> bool + Send + 'static,
+{
+    stream::unfold(Some((receiver, filter)), move |state| async move {
+        let (mut receiver, filter) = state?;
+        loop {
+            match receiver.recv().await {
+                Ok(job_event) if filter(&job_event) => {
+                    let Ok(event) = Event::default().event(job_event.kind.name()).json_data(&job_event) else {
+                        continue;
+                    };
+                    let next = match until_terminal && job_event.kind.is_terminal() {
+                        true => None,
+                        false => Some((receiver, filter)),
+                    };
+                    return Some((Ok(event), next));
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("job event subscriber lagged behind by {skipped} events");
+                    continue;
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
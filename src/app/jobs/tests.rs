@@ -0,0 +1,537 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{
+    body,
+    response::{sse::Sse, IntoResponse},
+};
+use serde_json::json;
+use tokio::sync::{broadcast, Semaphore};
+use uuid::Uuid;
+
+use crate::services::{
+    airtable::{
+        fake::{record, FakeAirtable},
+        schema::{Schema, Table},
+        webhooks::{TableChanges, WebhookPayload},
+        Airtable,
+    },
+    events::{JobEvent, JobEventKind},
+    storage::types::AirtableDatasourceViewMetadata,
+    workspace::users::WorkspaceUser,
+};
+
+use super::{
+    expand_record_links, fetch_airtable_records, job_event_stream, run_on_worker, webhooks::apply_table_changes,
+    workspace::workspace_user_record,
+};
+
+#[tokio::test]
+pub async fn test_fetch_airtable_records_for_import() {
+    let records = (0..5)
+        .map(|i| record(&format!("rec{i}"), json!({"fldFirst": format!("Person {i}")})))
+        .collect();
+
+    let fake = FakeAirtable::new()
+        .with_page_size(2)
+        .with_records("app1", "tblPeople", records);
+    let airtable = Airtable::new("token", &fake.serve().await);
+
+    // the field has been renamed since the view was created
+    let table = serde_json::from_value::<Table>(json!({
+        "id": "tblPeople",
+        "primaryFieldId": "fldFirst",
+        "name": "People",
+        "fields": [{"id": "fldFirst", "name": "Given Name", "type": "singleLineText"}],
+        "views": [{"id": "viwGrid", "name": "Grid view", "type": "grid"}]
+    }))
+    .unwrap();
+
+    let mut metadata = AirtableDatasourceViewMetadata {
+        is_user_table: false,
+        first_name_column: Some("fldFirst".into()),
+        last_name_column: None,
+        email_column: None,
+        base: "app1".into(),
+        table: "tblPeople".into(),
+        view: "viwGrid".into(),
+        fields: vec!["fldFirst".into()],
+        filter_formula: Some("{Active}".into()),
+        write_back: None,
+        link_expansions: vec![],
+        field_names: [("fldFirst".to_owned(), "First Name".to_owned())].into(),
+    };
+
+    let records = fetch_airtable_records(&airtable, &metadata, &table, None)
+        .await
+        .unwrap();
+
+    assert_eq!(records.len(), 5);
+    assert_eq!(records[4].fields["Given Name"], "Person 4");
+
+    let queries = fake.queries();
+    assert_eq!(queries.len(), 3);
+    assert!(queries[0].contains(&("filterByFormula".to_owned(), "{Active}".to_owned())));
+    assert!(queries[0].contains(&("returnFieldsByFieldId".to_owned(), "true".to_owned())));
+    assert!(queries[2].contains(&("offset".to_owned(), "4".to_owned())));
+
+    assert!(metadata.refresh_field_names(&table));
+    assert_eq!(metadata.field_name("fldFirst"), "Given Name");
+    assert!(!metadata.refresh_field_names(&table));
+}
+
+#[test]
+pub fn test_apply_webhook_table_changes() {
+    let table = serde_json::from_value::<Table>(json!({
+        "id": "tblPeople",
+        "primaryFieldId": "fldFirst",
+        "name": "People",
+        "fields": [
+            {"id": "fldFirst", "name": "First Name", "type": "singleLineText"},
+            {"id": "fldEmail", "name": "Email", "type": "email"},
+            {"id": "fldNotes", "name": "Notes", "type": "multilineText"}
+        ],
+        "views": [{"id": "viwGrid", "name": "Grid view", "type": "grid"}]
+    }))
+    .unwrap();
+
+    let metadata = serde_json::from_value::<AirtableDatasourceViewMetadata>(json!({
+        "isUserTable": false,
+        "base": "appPeople",
+        "table": "tblPeople",
+        "view": "viwGrid",
+        "fields": ["fldFirst", "fldEmail"]
+    }))
+    .unwrap();
+
+    let mut records = vec![
+        record("rec1", json!({"First Name": "Ada", "Email": "ada@example.com"})),
+        record("rec2", json!({"First Name": "Grace", "Email": "grace@example.com"})),
+        record("rec3", json!({"First Name": "Alan"})),
+    ];
+
+    let payload = serde_json::from_value::<WebhookPayload>(json!({
+        "timestamp": "2024-05-21T09:30:00.000Z",
+        "baseTransactionNumber": 42,
+        "payloadFormat": "v0",
+        "actionMetadata": {"source": "client", "sourceMetadata": {"user": {"id": "usr1"}}},
+        "changedTablesById": {
+            "tblPeople": {
+                "createdRecordsById": {
+                    "rec4": {
+                        "createdTime": "2024-05-21T09:29:59.000Z",
+                        "cellValuesByFieldId": {"fldFirst": "Katherine", "fldNotes": "not in the view"}
+                    }
+                },
+                "changedRecordsById": {
+                    "rec1": {
+                        "current": {"cellValuesByFieldId": {"fldEmail": "ada@lovelace.org"}},
+                        "previous": {"cellValuesByFieldId": {"fldEmail": "ada@example.com"}}
+                    },
+                    "rec2": {"current": {"cellValuesByFieldId": {"fldEmail": null}}},
+                    "rec5": {"current": {"cellValuesByFieldId": {"fldFirst": "Barbara"}}}
+                },
+                "destroyedRecordIds": ["rec3"],
+                "changedFieldsById": {"fldNotes": {"current": {"name": "Notes"}}},
+                "changedViewsById": {
+                    "viwGrid": {
+                        "createdRecordsById": {"rec4": {"createdTime": "2024-05-21T09:29:59.000Z"}},
+                        "changedRecordsById": {"rec5": {"current": {"cellValuesByFieldId": {"fldFirst": "Barbara"}}}}
+                    }
+                }
+            }
+        }
+    }))
+    .unwrap();
+
+    let applied = apply_table_changes(
+        &mut records,
+        &payload.changed_tables_by_id["tblPeople"],
+        &metadata,
+        &table,
+        &payload.timestamp,
+    );
+
+    assert_eq!(applied, 5);
+    assert_eq!(
+        records.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(),
+        ["rec1", "rec2", "rec4", "rec5"]
+    );
+    assert_eq!(
+        records[0].fields,
+        json!({"First Name": "Ada", "Email": "ada@lovelace.org"})
+    );
+    assert_eq!(records[1].fields, json!({"First Name": "Grace"}));
+    assert_eq!(records[2].fields, json!({"First Name": "Katherine"}));
+    assert_eq!(records[2].created_time, "2024-05-21T09:29:59.000Z");
+    assert_eq!(records[3].fields, json!({"First Name": "Barbara"}));
+}
+
+fn webhook_fixtures() -> (Table, AirtableDatasourceViewMetadata) {
+    let table = serde_json::from_value::<Table>(json!({
+        "id": "tblPeople",
+        "primaryFieldId": "fldFirst",
+        "name": "People",
+        "fields": [
+            {"id": "fldFirst", "name": "First Name", "type": "singleLineText"},
+            {"id": "fldEmail", "name": "Email", "type": "email"},
+            {"id": "fldActive", "name": "Active", "type": "checkbox"}
+        ],
+        "views": [{"id": "viwActive", "name": "Active people", "type": "grid"}]
+    }))
+    .unwrap();
+
+    let metadata = serde_json::from_value::<AirtableDatasourceViewMetadata>(json!({
+        "isUserTable": false,
+        "base": "appPeople",
+        "table": "tblPeople",
+        "view": "viwActive",
+        "fields": ["fldFirst", "fldEmail"]
+    }))
+    .unwrap();
+
+    (table, metadata)
+}
+
+#[test]
+pub fn test_apply_webhook_view_membership_changes() {
+    let (table, metadata) = webhook_fixtures();
+
+    let mut records = vec![
+        record("rec1", json!({"First Name": "Ada", "Email": "ada@example.com"})),
+        record("rec2", json!({"First Name": "Grace", "Email": "grace@example.com"})),
+    ];
+
+    let changes = serde_json::from_value::<TableChanges>(json!({
+        "createdRecordsById": {
+            "rec3": {"createdTime": "2024-05-21T09:29:59.000Z", "cellValuesByFieldId": {"fldFirst": "Alan"}}
+        },
+        "changedRecordsById": {
+            "rec1": {"current": {"cellValuesByFieldId": {"fldActive": false}}},
+            "rec4": {"current": {"cellValuesByFieldId": {"fldActive": true}}},
+            "rec5": {"current": {"cellValuesByFieldId": {"fldFirst": "Edsger"}}}
+        },
+        "changedViewsById": {
+            "viwActive": {
+                "changedRecordsById": {
+                    "rec4": {
+                        "current": {"cellValuesByFieldId": {"fldActive": true}},
+                        "unchanged": {"cellValuesByFieldId": {"fldFirst": "Katherine", "fldEmail": "kj@example.com"}}
+                    }
+                },
+                "destroyedRecordIds": ["rec1"]
+            }
+        }
+    }))
+    .unwrap();
+
+    let applied = apply_table_changes(&mut records, &changes, &metadata, &table, "2024-05-21T09:30:00.000Z");
+
+    // rec1 left the view, rec4 entered it, and rec3 and rec5 were never in it
+    assert_eq!(applied, 2);
+    assert_eq!(
+        records.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(),
+        ["rec2", "rec4"]
+    );
+    assert_eq!(
+        records[1].fields,
+        json!({"First Name": "Katherine", "Email": "kj@example.com"})
+    );
+    assert_eq!(records[1].created_time, "2024-05-21T09:30:00.000Z");
+}
+
+#[test]
+pub fn test_apply_webhook_merges_unchanged_cells() {
+    let (table, metadata) = webhook_fixtures();
+
+    let mut records = vec![record("rec1", json!({"First Name": "Ada"}))];
+
+    let changes = serde_json::from_value::<TableChanges>(json!({
+        "changedRecordsById": {
+            "rec1": {
+                "current": {"cellValuesByFieldId": {"fldFirst": "Augusta Ada"}},
+                "previous": {"cellValuesByFieldId": {"fldFirst": "Ada"}},
+                "unchanged": {"cellValuesByFieldId": {"fldEmail": "ada@example.com", "fldActive": true}}
+            }
+        }
+    }))
+    .unwrap();
+
+    let applied = apply_table_changes(&mut records, &changes, &metadata, &table, "2024-05-21T09:30:00.000Z");
+
+    assert_eq!(applied, 1);
+    assert_eq!(
+        records[0].fields,
+        json!({"First Name": "Augusta Ada", "Email": "ada@example.com"})
+    );
+}
+
+#[test]
+pub fn test_filtered_views_are_reimported_on_webhook_changes() {
+    let (_, mut metadata) = webhook_fixtures();
+    assert!(metadata.patch_from_webhooks());
+
+    metadata.filter_formula = Some("{Active}".into());
+    assert!(!metadata.patch_from_webhooks());
+}
+
+#[tokio::test]
+pub async fn test_expand_record_links() {
+    let schema = serde_json::from_value::<Schema>(json!({
+        "tables": [
+            {
+                "id": "tblPeople",
+                "primaryFieldId": "fldFirst",
+                "name": "People",
+                "fields": [
+                    {"id": "fldFirst", "name": "First Name", "type": "singleLineText"},
+                    {"id": "fldProject", "name": "Project", "type": "multipleRecordLinks", "options": {
+                        "linkedTableId": "tblProjects", "isReversed": false, "prefersSingleRecordLink": false
+                    }}
+                ],
+                "views": [{"id": "viwGrid", "name": "Grid view", "type": "grid"}]
+            },
+            {
+                "id": "tblProjects",
+                "primaryFieldId": "fldProjectName",
+                "name": "Projects",
+                "fields": [{"id": "fldProjectName", "name": "Project Name", "type": "singleLineText"}],
+                "views": []
+            }
+        ]
+    }))
+    .unwrap();
+
+    let projects = vec![
+        record("recWeb", json!({"fldProjectName": "Website Redesign"})),
+        record("recApp", json!({"fldProjectName": "Volunteer App"})),
+        record("recDocs", json!({"fldProjectName": "Documentation"})),
+    ];
+    let fake = FakeAirtable::new().with_records("appPeople", "tblProjects", projects);
+    let airtable = Airtable::new("token", &fake.serve().await);
+
+    let metadata = serde_json::from_value::<AirtableDatasourceViewMetadata>(json!({
+        "isUserTable": false,
+        "base": "appPeople",
+        "table": "tblPeople",
+        "view": "viwGrid",
+        "fields": ["fldFirst", "fldProject"],
+        "linkExpansions": [{"field": "fldProject", "linkedTable": "tblProjects", "displayField": "fldProjectName"}]
+    }))
+    .unwrap();
+
+    let mut records = vec![
+        record("rec1", json!({"First Name": "Ada", "Project": ["recWeb", "recApp"]})),
+        record("rec2", json!({"First Name": "Grace", "Project": ["recGone"]})),
+        record("rec3", json!({"First Name": "Alan"})),
+    ];
+
+    expand_record_links(&airtable, &metadata, &schema, &mut records)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        records[0].fields["Project"],
+        json!(["Website Redesign", "Volunteer App"])
+    );
+    assert_eq!(records[1].fields["Project"], json!(["recGone"]));
+    assert_eq!(records[2].fields, json!({"First Name": "Alan"}));
+
+    let queries = fake.queries();
+    assert_eq!(queries.len(), 1);
+    assert!(queries[0].contains(&("fields[]".to_owned(), "fldProjectName".to_owned())));
+    assert!(queries[0].contains(&(
+        "filterByFormula".to_owned(),
+        "OR(RECORD_ID()='recApp',RECORD_ID()='recGone',RECORD_ID()='recWeb')".to_owned()
+    )));
+
+    // expanding again leaves the display values alone
+    expand_record_links(&airtable, &metadata, &schema, &mut records)
+        .await
+        .unwrap();
+    assert_eq!(
+        records[0].fields["Project"],
+        json!(["Website Redesign", "Volunteer App"])
+    );
+    // and only asks again for the link that didn't resolve
+    let queries = fake.queries();
+    assert_eq!(queries.len(), 2);
+    assert!(queries[1].contains(&("filterByFormula".to_owned(), "OR(RECORD_ID()='recGone')".to_owned())));
+}
+
+#[tokio::test]
+pub async fn test_expand_record_links_in_batches() {
+    let schema = serde_json::from_value::<Schema>(json!({
+        "tables": [
+            {
+                "id": "tblPeople",
+                "primaryFieldId": "fldFirst",
+                "name": "People",
+                "fields": [
+                    {"id": "fldFirst", "name": "First Name", "type": "singleLineText"},
+                    {"id": "fldTeam", "name": "Team", "type": "multipleRecordLinks", "options": {
+                        "linkedTableId": "tblTeams", "isReversed": false, "prefersSingleRecordLink": true
+                    }}
+                ],
+                "views": [{"id": "viwGrid", "name": "Grid view", "type": "grid"}]
+            },
+            {
+                "id": "tblTeams",
+                "primaryFieldId": "fldTeamName",
+                "name": "Teams",
+                "fields": [{"id": "fldTeamName", "name": "Team Name", "type": "singleLineText"}],
+                "views": []
+            }
+        ]
+    }))
+    .unwrap();
+
+    let teams = (0..250)
+        .map(|i| record(&format!("recTeam{i}"), json!({"fldTeamName": format!("Team {i}")})))
+        .collect();
+    let fake = FakeAirtable::new().with_records("appPeople", "tblTeams", teams);
+    let airtable = Airtable::new("token", &fake.serve().await);
+
+    let metadata = serde_json::from_value::<AirtableDatasourceViewMetadata>(json!({
+        "isUserTable": false,
+        "base": "appPeople",
+        "table": "tblPeople",
+        "view": "viwGrid",
+        "fields": ["fldFirst", "fldTeam"],
+        "linkExpansions": [{"field": "fldTeam", "displayField": "fldTeamName"}]
+    }))
+    .unwrap();
+
+    let mut records = (0..150)
+        .map(|i| record(&format!("rec{i}"), json!({"Team": [format!("recTeam{}", i % 120)]})))
+        .collect::<Vec<_>>();
+
+    expand_record_links(&airtable, &metadata, &schema, &mut records)
+        .await
+        .unwrap();
+
+    assert_eq!(records[0].fields["Team"], json!(["Team 0"]));
+    assert_eq!(records[149].fields["Team"], json!(["Team 29"]));
+
+    // 120 distinct teams are linked, out of 250
+    assert_eq!(fake.queries().len(), 2);
+}
+
+#[test]
+pub fn test_workspace_user_record() {
+    let user = serde_json::from_value::<WorkspaceUser>(json!({
+        "id": "1001",
+        "primaryEmail": "ada@example.org",
+        "name": {"givenName": "Ada", "familyName": "Lovelace", "fullName": "Ada Lovelace"},
+        "orgUnitPath": "/Volunteers",
+        "creationTime": "2024-05-01T00:00:00.000Z",
+        "lastLoginTime": "1970-01-01T00:00:00.000Z",
+        "emails": [
+            {"address": "ada@example.org", "primary": true},
+            {"address": "countess@example.org"}
+        ],
+        "suspended": true
+    }))
+    .unwrap();
+
+    let record = workspace_user_record(user);
+
+    assert_eq!(record.id, "1001");
+    assert_eq!(record.created_time, "2024-05-01T00:00:00.000Z");
+    assert_eq!(record.fields["Primary Email"], "ada@example.org");
+    assert_eq!(record.fields["Full Name"], "Ada Lovelace");
+    assert_eq!(record.fields["Org Unit"], "/Volunteers");
+    assert_eq!(record.fields["Aliases"], json!(["countess@example.org"]));
+    assert_eq!(record.fields["Suspended"], true);
+    assert_eq!(record.fields["Last Login"], json!(null));
+    assert_eq!(record.fields["Recovery Email"], json!(null));
+}
+
+#[test]
+pub fn test_job_event_serialization() {
+    let job_id = Uuid::new_v4();
+    let event = JobEvent {
+        job_id,
+        datasource_view_id: None,
+        kind: JobEventKind::Progress {
+            processed_items: 3,
+            total_items: Some(10),
+        },
+    };
+
+    assert_eq!(
+        serde_json::to_value(&event).unwrap(),
+        json!({"jobId": job_id, "datasourceViewId": null, "event": "progress", "processedItems": 3, "totalItems": 10})
+    );
+    assert_eq!(event.kind.name(), "progress");
+    assert!(!event.kind.is_terminal());
+    assert!(JobEventKind::Errored { error: "failed".into() }.is_terminal());
+}
+
+#[tokio::test]
+pub async fn test_job_event_stream_filters_and_ends_at_terminal_event() {
+    let (sender, receiver) = broadcast::channel(16);
+    let (job_id, other_job_id) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let event = |job_id, kind| JobEvent {
+        job_id,
+        datasource_view_id: None,
+        kind,
+    };
+    for e in [
+        event(job_id, JobEventKind::Started { total_items: Some(1) }),
+        event(other_job_id, JobEventKind::Complete),
+        event(job_id, JobEventKind::Complete),
+        event(job_id, JobEventKind::Started { total_items: None }),
+    ] {
+        sender.send(e).unwrap();
+    }
+
+    // the stream ends after the complete event, so the response body can be read to its end
+    let events = job_event_stream(receiver, move |e| e.job_id == job_id, true);
+    let body = Sse::new(events).into_response().into_body();
+    let body = String::from_utf8(body::to_bytes(body, usize::MAX).await.unwrap().to_vec()).unwrap();
+
+    let frames = body.split_terminator("\n\n").collect::<Vec<&str>>();
+    assert_eq!(frames.len(), 2, "{body}");
+    assert!(frames[0].starts_with("event: started\ndata: {"), "{body}");
+    assert!(frames[1].starts_with("event: complete\ndata: {"), "{body}");
+    assert!(!body.contains(&other_job_id.to_string()), "{body}");
+}
+
+#[tokio::test]
+pub async fn test_run_on_worker_caps_concurrent_jobs() {
+    let workers = Arc::new(Semaphore::new(2));
+    let (running, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+
+    let handles = (0..6)
+        .map(|_| {
+            let (running, peak) = (running.clone(), peak.clone());
+            tokio::spawn(run_on_worker(workers.clone(), async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            }))
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+    // a closed pool drops the job instead of running it
+    workers.close();
+    let ran = Arc::new(AtomicUsize::new(0));
+    let counter = ran.clone();
+    run_on_worker(workers, async move {
+        counter.fetch_add(1, Ordering::SeqCst);
+    })
+    .await;
+    assert_eq!(ran.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::{
+    services::{
+        events::{JobEvent, JobEventKind},
+        storage::{dto::CreateJobItemBuilder, types::JobItemStatus},
+    },
+    state::AppState,
+};
+
+// Records a running job's lifecycle in the database and publishes each transition to event subscribers.
+#[derive(Clone)]
+pub struct JobTracker {
+    state: AppState,
+    pub job_id: Uuid,
+    pub datasource_view_id: Option<Uuid>,
+}
+
+// Touches a job while it is queued or running, so it isn't taken for abandoned. Stops when dropped.
+pub struct Heartbeat(JoinHandle<()>);
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl JobTracker {
+    pub fn new(state: AppState, job_id: Uuid, datasource_view_id: Option<Uuid>) -> Self {
+        Self {
+            state,
+            job_id,
+            datasource_view_id,
+        }
+    }
+
+    async fn publish(&self, kind: JobEventKind) {
+        let event = JobEvent {
+            job_id: self.job_id,
+            datasource_view_id: self.datasource_view_id,
+            kind,
+        };
+        self.state.events.publish(&self.state.storage.cache, event).await;
+    }
+
+    // Beats a few times per `stale_job_after`, so a beat can be missed without the job being failed as abandoned.
+    pub fn heartbeat(&self) -> Heartbeat {
+        let tracker = self.clone();
+        let every = (self.state.settings.stale_job_after / 3).max(Duration::from_secs(1));
+
+        Heartbeat(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                if let Err(e) = tracker.state.storage.db.touch_job(tracker.job_id).await {
+                    log::warn!("unable to record a heartbeat for job {}: {e:#}", tracker.job_id);
+                }
+            }
+        }))
+    }
+
+    pub async fn start(&self, total_items: Option<i32>) -> Result<()> {
+        self.state.storage.db.mark_job_started(self.job_id, total_items).await?;
+        self.publish(JobEventKind::Started { total_items }).await;
+        Ok(())
+    }
+
+    pub async fn progress(&self, processed_items: i32, total_items: Option<i32>) -> Result<()> {
+        self.state
+            .storage
+            .db
+            .update_job_progress(self.job_id, processed_items, total_items)
+            .await?;
+        self.publish(JobEventKind::Progress {
+            processed_items,
+            total_items,
+        })
+        .await;
+        Ok(())
+    }
+
+    pub async fn record_item(&self, item: &str, status: JobItemStatus, reason: Option<String>) -> Result<()> {
+        let dto = CreateJobItemBuilder::default()
+            .job_id(self.job_id)
+            .item(item)
+            .status(status)
+            .reason(reason)
+            .build()?;
+
+        let (processed_items, total_items) = self.state.storage.db.record_job_item(dto).await?;
+        self.publish(JobEventKind::Progress {
+            processed_items,
+            total_items,
+        })
+        .await;
+        Ok(())
+    }
+
+    // Finishing is a no op once the job has finished, e.g. when it was already failed as abandoned.
+    pub async fn complete(&self) -> Result<()> {
+        if self.state.storage.db.mark_job_complete(self.job_id).await? {
+            self.publish(JobEventKind::Complete).await;
+        }
+        Ok(())
+    }
+
+    pub async fn fail(&self, error: &str) -> Result<()> {
+        if self.state.storage.db.mark_job_errored(self.job_id, error).await? {
+            self.publish(JobEventKind::Errored {
+                error: error.to_owned(),
+            })
+            .await;
+        }
+        Ok(())
+    }
+}
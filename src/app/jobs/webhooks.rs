@@ -0,0 +1,311 @@
+use std::collections::HashSet;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::{
+    services::{
+        airtable::{record::Record, schema::Table, webhooks::TableChanges},
+        storage::{
+            diff_records,
+            dto::CreateAirtableWebhookBuilder,
+            entities::AirtableWebhook,
+            errors::JobConflict,
+            types::{AirtableDatasourceViewMetadata, RecordChangeType},
+        },
+    },
+    state::AppState,
+};
+
+use super::{cached_schema, expand_record_links, start_import_job};
+
+const SYNC_LEASE_SECONDS: i64 = 300;
+
+// Registers a webhook for changes to the view's records, if airtable has a public address to send notifications to.
+pub async fn register_airtable_webhook(
+    state: &AppState,
+    view_id: Uuid,
+    metadata: &AirtableDatasourceViewMetadata,
+) -> Result<()> {
+    let Some(ref public_base_uri) = state.settings.public_base_uri else {
+        return Ok(());
+    };
+
+    let notification_url = format!("{public_base_uri}/webhooks/airtable");
+    let webhook = state
+        .airtable
+        .create_webhook(&metadata.base, &notification_url, &metadata.view, &metadata.fields)
+        .await?;
+
+    let dto = CreateAirtableWebhookBuilder::default()
+        .datasource_view_id(view_id)
+        .base_id(metadata.base.clone())
+        .webhook_id(webhook.id)
+        .mac_secret(webhook.mac_secret_base64)
+        .expires_at(parse_time(webhook.expiration_time))
+        .build()?;
+
+    state.storage.db.create_airtable_webhook(dto).await
+}
+
+// Deletes the view's webhook from Airtable and forgets it, if one was registered.
+pub async fn unregister_airtable_webhook(state: &AppState, view_id: Uuid) -> Result<()> {
+    let db = &state.storage.db;
+
+    let Some(webhook) = db.fetch_view_airtable_webhook(view_id).await? else {
+        return Ok(());
+    };
+
+    state
+        .airtable
+        .delete_webhook(&webhook.base_id, &webhook.webhook_id)
+        .await?;
+
+    db.delete_airtable_webhook(&webhook.webhook_id).await
+}
+
+// Fetches the webhook's new payloads cursor by cursor and applies their record changes to the view's cached records.
+pub async fn sync_airtable_webhook(state: &AppState, webhook_id: &str) -> Result<()> {
+    let db = &state.storage.db;
+
+    let Some(webhook) = db.claim_airtable_webhook_sync(webhook_id, SYNC_LEASE_SECONDS).await? else {
+        log::info!("skipping sync of webhook {webhook_id}, it is already being synced");
+        return Ok(());
+    };
+
+    let result = apply_webhook_payloads(state, &webhook).await;
+
+    // payloads are only marked as read once their changes have been saved
+    let cursor = *result.as_ref().unwrap_or(&webhook.cursor);
+    db.release_airtable_webhook_sync(webhook_id, cursor).await?;
+
+    result.map(|_| ())
+}
+
+async fn apply_webhook_payloads(state: &AppState, webhook: &AirtableWebhook) -> Result<i64> {
+    let db = &state.storage.db;
+
+    let view = db
+        .fetch_datasource_view(webhook.datasource_view_id)
+        .await?
+        .context("webhook view no longer exists")?;
+    let metadata = serde_json::from_value::<AirtableDatasourceViewMetadata>(view.metadata.clone())?;
+
+    let schema = cached_schema(state, &metadata.base).await?;
+    let table = schema
+        .table(&metadata.table)
+        .with_context(|| format!("base {} has no table {}", metadata.base, metadata.table))?;
+
+    // a view that hasn't been imported yet gets these changes with its first import, so its payloads are only skipped
+    let mut records = state.storage.fetch_view_records(view.id).await?;
+    let cached = records.clone().unwrap_or_default();
+
+    let reimport = !metadata.patch_from_webhooks();
+
+    let mut cursor = webhook.cursor;
+    let mut applied = 0;
+    let mut changed = false;
+
+    loop {
+        let page = state
+            .airtable
+            .list_webhook_payloads(&webhook.base_id, &webhook.webhook_id, cursor)
+            .await?;
+
+        for payload in &page.payloads {
+            let (Some(records), Some(changes)) = (records.as_mut(), payload.changed_tables_by_id.get(&table.id)) else {
+                continue;
+            };
+            changed = true;
+            if !reimport {
+                applied += apply_table_changes(records, changes, &metadata, table, &payload.timestamp);
+            }
+        }
+
+        cursor = page.cursor;
+        if !page.might_have_more {
+            break;
+        }
+    }
+
+    if reimport {
+        if changed {
+            match start_import_job(state.clone(), view.user_id, &view).await {
+                Ok((job_id, _)) => log::info!("re-importing {} as job {job_id} after webhook changes", view.id),
+                Err(e) if e.is::<JobConflict>() => {
+                    log::info!("skipping re-import of {}, an import is already running", view.id)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        return Ok(cursor);
+    }
+
+    if let Some(mut records) = records.filter(|_| applied > 0) {
+        // changes carry raw record ids in link fields
+        expand_record_links(&state.airtable, &metadata, &schema, &mut records).await?;
+
+        // only the records the payloads touched are saved, so an import saved since they were read isn't undone
+        let changes = diff_records(&cached, &records);
+        let touched = changes
+            .iter()
+            .map(|c| c.source_record_id.as_str())
+            .collect::<HashSet<&str>>();
+        let removed = changes
+            .iter()
+            .filter(|c| c.change_type == RecordChangeType::Removed)
+            .map(|c| c.source_record_id.clone())
+            .collect::<Vec<String>>();
+        records.retain(|r| touched.contains(r.id.as_str()));
+
+        state
+            .storage
+            .save_view_record_changes(view.id, &records, &removed)
+            .await?;
+    }
+
+    log::info!(
+        "applied {applied} record changes to {} from webhook {}",
+        view.id,
+        webhook.webhook_id
+    );
+
+    Ok(cursor)
+}
+
+// Applies a payload's record changes to cached records, keeping only the view's fields and keying them by name like
+// an import does. Records are added when they enter the view and removed when they leave it or are destroyed.
+// Returns the number of records touched.
+pub fn apply_table_changes(
+    records: &mut Vec<Record<Value>>,
+    changes: &TableChanges,
+    metadata: &AirtableDatasourceViewMetadata,
+    table: &Table,
+    timestamp: &str,
+) -> usize {
+    let cells = |cells: &Map<String, Value>| -> Map<String, Value> {
+        cells
+            .iter()
+            .filter_map(|(id, value)| {
+                let field = table.field(id)?;
+                let watched = metadata.fields.iter().any(|f| *f == field.id || *f == field.name);
+                watched.then(|| (field.name.clone(), value.clone()))
+            })
+            .collect()
+    };
+
+    let view_changes = table
+        .view(&metadata.view)
+        .and_then(|view| changes.changed_views_by_id.get(&view.id));
+    let in_view = |id: &String| {
+        view_changes
+            .is_some_and(|v| v.created_records_by_id.contains_key(id) || v.changed_records_by_id.contains_key(id))
+    };
+
+    let removed = changes
+        .destroyed_record_ids
+        .iter()
+        .chain(view_changes.iter().flat_map(|v| &v.destroyed_record_ids))
+        .collect::<HashSet<&String>>();
+    let before = records.len();
+    records.retain(|r| !removed.contains(&r.id));
+    let mut applied = before - records.len();
+
+    // a record created in the table only belongs in the cache if it was created in the view
+    let created = changes
+        .created_records_by_id
+        .iter()
+        .filter(|(id, _)| in_view(id))
+        .chain(view_changes.iter().flat_map(|v| &v.created_records_by_id));
+    for (id, created) in created {
+        let mut fields = cells(&created.cell_values_by_field_id);
+        match records.iter_mut().find(|r| r.id == *id) {
+            Some(record) => {
+                if let Value::Object(ref mut cached) = record.fields {
+                    cached.append(&mut fields);
+                }
+                continue;
+            }
+            None => records.push(Record {
+                id: id.clone(),
+                fields: Value::Object(fields),
+                created_time: created.created_time.clone(),
+            }),
+        }
+        applied += 1;
+    }
+
+    let changed = changes
+        .changed_records_by_id
+        .iter()
+        .chain(view_changes.iter().flat_map(|v| &v.changed_records_by_id));
+    let mut touched = HashSet::new();
+    for (id, changed) in changed {
+        let i = match records.iter().position(|r| r.id == *id) {
+            Some(i) => i,
+            // a change to a record outside the view
+            None if !in_view(id) => continue,
+            None => {
+                // the record has just entered the view, and change payloads don't say when it was created
+                records.push(Record {
+                    id: id.clone(),
+                    fields: Value::Object(Map::new()),
+                    created_time: timestamp.to_owned(),
+                });
+                records.len() - 1
+            }
+        };
+
+        let Value::Object(ref mut fields) = records[i].fields else {
+            continue;
+        };
+
+        // cleared cells come through as null, while imported records leave empty fields out
+        let values = cells(&changed.unchanged.cell_values_by_field_id)
+            .into_iter()
+            .chain(cells(&changed.current.cell_values_by_field_id));
+        for (name, value) in values {
+            match value {
+                Value::Null => fields.remove(&name),
+                value => fields.insert(name, value),
+            };
+        }
+        if touched.insert(id) {
+            applied += 1;
+        }
+    }
+
+    applied
+}
+
+// Webhooks expire a week after they are created or refreshed, so any expiring within a day are refreshed.
+pub async fn refresh_expiring_webhooks(state: &AppState) -> Result<()> {
+    let db = &state.storage.db;
+
+    let webhooks = db
+        .fetch_expiring_airtable_webhooks(Utc::now() + Duration::days(1))
+        .await?;
+
+    for webhook in webhooks {
+        match state
+            .airtable
+            .refresh_webhook(&webhook.base_id, &webhook.webhook_id)
+            .await
+        {
+            Ok(refreshed) => {
+                db.set_airtable_webhook_expiry(&webhook.webhook_id, parse_time(refreshed.expiration_time))
+                    .await?
+            }
+            Err(e) => log::warn!("unable to refresh webhook {}: {e:#}", webhook.webhook_id),
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_time(time: Option<String>) -> Option<DateTime<Utc>> {
+    time.and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    services::{
+        airtable::record::Record, storage::types::WorkspaceDatasourceViewMetadata, workspace::users::WorkspaceUser,
+    },
+    state::AppState,
+};
+
+use super::JobTracker;
+
+// what the directory reports as the last login of users who never signed in
+const NEVER_LOGGED_IN: &str = "1970-01-01T00:00:00.000Z";
+
+// Imports the directory users matching the view's filter as its records, keyed by their directory ids.
+pub async fn fetch_and_cache_workspace_users(
+    state: AppState,
+    tracker: &JobTracker,
+    view_id: Uuid,
+    metadata: WorkspaceDatasourceViewMetadata,
+) -> Result<()> {
+    tracker.start(None).await?;
+
+    let users = state
+        .workspace_client
+        .list_users(&metadata.admin_email, &metadata.filter)
+        .await?;
+
+    let records = users
+        .into_iter()
+        .map(workspace_user_record)
+        .collect::<Vec<Record<Value>>>();
+    let total = records.len() as i32;
+
+    let changes = state
+        .storage
+        .save_view_records(view_id, Some(tracker.job_id), &records)
+        .await?;
+    log::info!("import of {view_id} found {} changed records", changes.len());
+    tracker.progress(total, Some(total)).await?;
+
+    Ok(())
+}
+
+// Flattens a directory user into the fields its record is browsed and filtered by.
+pub fn workspace_user_record(user: WorkspaceUser) -> Record<Value> {
+    let aliases = user
+        .emails
+        .iter()
+        .filter(|e| e.address != user.primary_email)
+        .map(|e| e.address.clone())
+        .collect::<Vec<String>>();
+
+    let last_login =
+        (!user.last_login_time.is_empty() && user.last_login_time != NEVER_LOGGED_IN).then_some(user.last_login_time);
+
+    Record {
+        id: user.id,
+        created_time: user.creation_time,
+        fields: json!({
+            "Primary Email": user.primary_email,
+            "First Name": user.name.given_name,
+            "Last Name": user.name.family_name,
+            "Full Name": user.name.full_name,
+            "Org Unit": user.org_unit_path,
+            "Recovery Email": user.recovery_email,
+            "Aliases": aliases,
+            "Admin": user.is_admin,
+            "Suspended": user.suspended,
+            "Archived": user.archived,
+            "2-Step Verification": user.is_enrolled_in2sv,
+            "Last Login": last_login,
+        }),
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::Result;
+use axum::{
+    body::{self, Body},
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    app::errors::AppError,
+    services::{
+        auth::userdata::UserData,
+        storage::{dto::ClaimIdempotencyKeyBuilder, entities::IdempotencyKey},
+    },
+    state::AppState,
+};
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+const MAX_REQUEST_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+// Replays the stored response when a request is retried with the same `Idempotency-Key` header. Keys are scoped to
+// the authenticated user and are bound to a hash of the method, path and body they were first used with.
+pub async fn idempotency(
+    State(state): State<AppState>,
+    Extension(user_info): Extension<UserData>,
+    req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let db = &state.storage.db;
+
+    let Some(key) = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned)
+    else {
+        return Ok(next.run(req).await);
+    };
+
+    if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+        return Ok((StatusCode::BAD_REQUEST, "invalid idempotency key").into_response());
+    }
+
+    let UserData::Auth0(user_info) = user_info;
+
+    let (parts, body) = req.into_parts();
+    let Ok(bytes) = body::to_bytes(body, MAX_REQUEST_BODY_BYTES).await else {
+        return Ok((StatusCode::PAYLOAD_TOO_LARGE).into_response());
+    };
+
+    let path = parts.uri.path().to_owned();
+    let request_hash = request_hash(parts.method.as_str(), &path, &bytes);
+
+    let dto = ClaimIdempotencyKeyBuilder::default()
+        .owner(user_info.sub.clone())
+        .idempotency_key(key.clone())
+        .request_path(path)
+        .request_hash(request_hash.clone())
+        .build()?;
+
+    let window = state.settings.idempotency_window.as_secs() as i64;
+
+    let Some(claim_id) = db.claim_idempotency_key(dto, window).await? else {
+        let existing = db.fetch_idempotency_key(&user_info.sub, &key).await?;
+        return Ok(replay(existing, &request_hash)?);
+    };
+
+    // if this future is dropped before the response is saved, e.g. because the client hung up, the claim is let go
+    // so a retry can run instead of being told the request is still in progress until the window ends
+    let guard = ClaimGuard::new({
+        let state = state.clone();
+        move || {
+            tokio::spawn(async move {
+                if let Err(e) = state.storage.db.release_idempotency_key(claim_id).await {
+                    log::warn!("unable to release abandoned idempotency key {claim_id}: {e:#}");
+                }
+            });
+        }
+    });
+
+    let res = next.run(Request::from_parts(parts, Body::from(bytes))).await;
+
+    // server errors are not remembered so the client can retry with the same key
+    if res.status().is_server_error() {
+        guard.disarm();
+        db.release_idempotency_key(claim_id).await?;
+        return Ok(res);
+    }
+
+    let (parts, body) = res.into_parts();
+    let bytes = body::to_bytes(body, usize::MAX).await?;
+
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+
+    db.save_idempotent_response(claim_id, parts.status.as_u16() as i32, content_type, bytes.to_vec())
+        .await?;
+    guard.disarm();
+
+    Ok(Response::from_parts(parts, Body::from(bytes)))
+}
+
+// Runs `release` when dropped, unless it was disarmed first.
+pub struct ClaimGuard<F: FnOnce()>(Option<F>);
+
+impl<F: FnOnce()> ClaimGuard<F> {
+    pub fn new(release: F) -> Self {
+        Self(Some(release))
+    }
+
+    pub fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl<F: FnOnce()> Drop for ClaimGuard<F> {
+    fn drop(&mut self) {
+        if let Some(release) = self.0.take() {
+            release();
+        }
+    }
+}
+
+pub fn request_hash(method: &str, path: &str, body: &[u8]) -> String {
+    format!(
+        "{:x}",
+        Sha256::new()
+            .chain_update(method)
+            .chain_update(path)
+            .chain_update(body)
+            .finalize()
+    )
+}
+
+// Decides what a request gets when its key was already claimed: the stored response if the first request finished,
+// otherwise an error explaining why it can't be replayed.
+pub fn replay(existing: Option<IdempotencyKey>, request_hash: &str) -> Result<Response> {
+    let Some(existing) = existing else {
+        return Ok((StatusCode::CONFLICT, "idempotency key is being reused concurrently").into_response());
+    };
+
+    if existing.request_hash != request_hash {
+        return Ok((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "idempotency key was already used with a different request",
+        )
+            .into_response());
+    }
+
+    let (Some(status_code), Some(response_body)) = (existing.status_code, existing.response_body) else {
+        return Ok((
+            StatusCode::CONFLICT,
+            "a request with this idempotency key is still in progress",
+        )
+            .into_response());
+    };
+
+    let mut res = Response::new(Body::from(response_body));
+    *res.status_mut() = StatusCode::from_u16(status_code as u16)?;
+    if let Some(content_type) = existing.content_type.and_then(|c| HeaderValue::from_str(&c).ok()) {
+        res.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    res.headers_mut()
+        .insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    Ok(res)
+}
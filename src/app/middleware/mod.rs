@@ -1,3 +1,7 @@
+mod idempotency;
+#[cfg(test)]
+mod tests;
+
 use anyhow::{bail, Result};
 use axum::{
     extract::{Request, State},
@@ -13,6 +17,8 @@ use crate::{services::auth::userdata::UserData, state::AppState};
 
 use super::errors::AppError;
 
+pub use idempotency::idempotency;
+
 pub async fn auth(
     State(state): State<AppState>,
     auth_header: TypedHeader<Authorization<Bearer>>,
@@ -0,0 +1,79 @@
+use std::cell::Cell;
+
+use axum::{
+    body,
+    http::{header, StatusCode},
+};
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::services::storage::entities::IdempotencyKey;
+
+use super::idempotency::{replay, request_hash, ClaimGuard};
+
+fn claimed(request_hash: &str, response: Option<(i32, &str)>) -> IdempotencyKey {
+    IdempotencyKey {
+        id: Uuid::new_v4(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        owner: "auth0|user".into(),
+        idempotency_key: "key".into(),
+        request_path: "/api/v1/datasource".into(),
+        request_hash: request_hash.into(),
+        status_code: response.map(|(status, _)| status),
+        content_type: response.map(|_| "application/json".into()),
+        response_body: response.map(|(_, body)| body.as_bytes().to_vec()),
+    }
+}
+
+#[test]
+pub fn test_request_hash() {
+    let hash = request_hash("POST", "/api/v1/datasource", br#"{"name":"a"}"#);
+    assert_eq!(hash.len(), 64);
+    assert_eq!(hash, request_hash("POST", "/api/v1/datasource", br#"{"name":"a"}"#));
+
+    assert_ne!(hash, request_hash("PUT", "/api/v1/datasource", br#"{"name":"a"}"#));
+    assert_ne!(hash, request_hash("POST", "/api/v1/datasource/1", br#"{"name":"a"}"#));
+    assert_ne!(hash, request_hash("POST", "/api/v1/datasource", br#"{"name":"b"}"#));
+}
+
+#[tokio::test]
+pub async fn test_replay_stored_response() {
+    let hash = request_hash("POST", "/api/v1/datasource", b"{}");
+
+    let res = replay(Some(claimed(&hash, Some((201, r#"{"id":1}"#)))), &hash).unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+    assert_eq!(res.headers()["idempotent-replayed"], "true");
+    let bytes = body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&bytes[..], br#"{"id":1}"#);
+}
+
+#[test]
+pub fn test_replay_refuses_what_it_cannot_replay() {
+    let hash = request_hash("POST", "/api/v1/datasource", b"{}");
+
+    // the key was claimed and released again between our claim and fetch
+    assert_eq!(replay(None, &hash).unwrap().status(), StatusCode::CONFLICT);
+
+    let other = request_hash("POST", "/api/v1/datasource", b"[]");
+    let res = replay(Some(claimed(&other, Some((201, "{}")))), &hash).unwrap();
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let res = replay(Some(claimed(&hash, None)), &hash).unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    assert!(res.headers().get("idempotent-replayed").is_none());
+}
+
+#[test]
+pub fn test_abandoned_claims_are_released() {
+    let released = Cell::new(0);
+
+    // the handler future was dropped before its response was saved
+    drop(ClaimGuard::new(|| released.set(released.get() + 1)));
+    assert_eq!(released.get(), 1);
+
+    // the response was saved, or the claim released on the spot
+    ClaimGuard::new(|| released.set(released.get() + 1)).disarm();
+    assert_eq!(released.get(), 1);
+}
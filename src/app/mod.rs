@@ -9,6 +9,8 @@ use tower_http::{cors::CorsLayer, trace::TraceLayer};
 
 use crate::state::AppState;
 
+pub use jobs::run_refresh_scheduler;
+
 pub fn routes(state: AppState) -> Router<()> {
     tracing_subscriber::fmt().with_max_level(tracing::Level::DEBUG).init();
 
@@ -18,7 +20,9 @@ pub fn routes(state: AppState) -> Router<()> {
         .with_state(state.clone())
         .nest("/api", api)
         .route("/health", routing::get(controllers::health_check))
-        .route_layer(axum::middleware::from_fn_with_state(state, middleware::auth))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), middleware::auth))
+        // added after the auth layer so notifications from airtable don't need a bearer token
+        .nest("/webhooks", api::webhook_routes(state))
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
 }
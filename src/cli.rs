@@ -16,10 +16,28 @@ pub struct Args {
     pub workspace_token_uri: String,
     #[arg(long, env)]
     pub airtable_api_token: String,
+    #[arg(long, env, default_value = "https://api.airtable.com/v0")]
+    pub airtable_base_uri: String,
     #[arg(long, env)]
     pub database_url: String,
     #[arg(long, env)]
     pub cache_url: String,
     #[arg(long, env)]
     pub sendgrid_api_key: String,
+    #[arg(long, env, default_value_t = false)]
+    pub job_events_fanout: bool,
+    #[arg(long, env, default_value_t = 60)]
+    pub refresh_scheduler_tick_seconds: u64,
+    #[arg(long, env, default_value_t = 4)]
+    pub max_concurrent_jobs: usize,
+    #[arg(long, env, default_value_t = 86400)]
+    pub idempotency_window_seconds: u64,
+    #[arg(long, env, default_value_t = 300)]
+    pub airtable_cache_ttl_seconds: u64,
+    // pending jobs that report no progress for this long are treated as abandoned
+    #[arg(long, env, default_value_t = 1800)]
+    pub stale_job_after_seconds: u64,
+    // the externally reachable address of this server, needed to receive airtable webhook notifications
+    #[arg(long, env)]
+    pub public_base_uri: Option<String>,
 }
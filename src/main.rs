@@ -4,16 +4,17 @@ mod services;
 mod state;
 
 use sendgrid::SGClient;
-use std::collections::HashMap;
-use tokio::sync::Mutex;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{Mutex, Semaphore};
 
 use clap::Parser;
 use cli::Args;
-use state::{AppState, State};
+use state::{AppState, Settings, State};
 
 use services::{
     airtable::Airtable,
     auth::auth0::Auth0,
+    events::JobEvents,
     storage::{Cache, Sql, Storage},
 };
 
@@ -38,7 +39,7 @@ async fn main() {
         &args.workspace_token_uri,
     ));
 
-    let airtable = Airtable::new(&args.airtable_api_token);
+    let airtable = Airtable::new(&args.airtable_api_token, &args.airtable_base_uri);
 
     let sql = Sql::new(&args.database_url)
         .await
@@ -48,6 +49,13 @@ async fn main() {
 
     sqlx::migrate!().run(&sql.pool).await.expect("error running migrations");
 
+    // jobs left pending by a previous run of this or another instance will never finish
+    match sql.fail_stale_jobs(args.stale_job_after_seconds as i64).await {
+        Ok(0) => {}
+        Ok(failed) => log::warn!("marked {failed} abandoned jobs as errored"),
+        Err(e) => log::warn!("could not clean up abandoned jobs: {e}"),
+    }
+
     let db = Storage { db: sql, cache };
 
     let state = AppState::new(State {
@@ -57,8 +65,33 @@ async fn main() {
         storage: db,
         tasks: Mutex::new(HashMap::new()),
         mail: SGClient::new(&args.sendgrid_api_key),
+        events: JobEvents::new(args.job_events_fanout),
+        workers: Arc::new(Semaphore::new(args.max_concurrent_jobs)),
+        settings: Settings {
+            idempotency_window: Duration::from_secs(args.idempotency_window_seconds),
+            airtable_cache_ttl: Duration::from_secs(args.airtable_cache_ttl_seconds),
+            stale_job_after: Duration::from_secs(args.stale_job_after_seconds),
+            public_base_uri: args.public_base_uri.map(|uri| uri.trim_end_matches('/').to_owned()),
+        },
     });
 
+    if args.job_events_fanout {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = state.events.relay(&state.storage.cache).await {
+                    log::warn!("job event relay disconnected: {e}");
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+    }
+
+    tokio::spawn(app::run_refresh_scheduler(
+        state.clone(),
+        Duration::from_secs(args.refresh_scheduler_tick_seconds),
+    ));
+
     let router = app::routes(state);
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8888")
         .await
@@ -0,0 +1,48 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+
+// An error response from the Airtable API, carrying the `error.type` and `error.message` it was returned with.
+#[derive(Debug)]
+pub struct AirtableError {
+    pub status: StatusCode,
+    pub error_type: String,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: Value,
+}
+
+impl AirtableError {
+    // Airtable sends `{"error": {"type": .., "message": ..}}`, or just `{"error": "NOT_FOUND"}` for some requests.
+    pub fn from_body(status: StatusCode, body: &str) -> Self {
+        let error = serde_json::from_str::<ErrorResponse>(body).map(|r| r.error).ok();
+
+        let (error_type, message) = match error {
+            Some(Value::Object(error)) => (
+                error.get("type").and_then(Value::as_str).map(str::to_owned),
+                error.get("message").and_then(Value::as_str).map(str::to_owned),
+            ),
+            Some(Value::String(error_type)) => (Some(error_type), None),
+            _ => (None, None),
+        };
+
+        Self {
+            status,
+            error_type: error_type.unwrap_or_else(|| status.as_str().to_owned()),
+            message: message.unwrap_or_else(|| body.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for AirtableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "airtable {} ({}): {}", self.error_type, self.status, self.message)
+    }
+}
+
+impl std::error::Error for AirtableError {}
@@ -0,0 +1,342 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing, Json, Router,
+};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use super::{
+    bases::{Base, Bases},
+    record::Record,
+    schema::Schema,
+};
+
+#[derive(Default)]
+struct FakeState {
+    bases: Vec<Base>,
+    schemas: HashMap<String, Schema>,
+    records: HashMap<(String, String), Vec<Record<Value>>>,
+    failures: VecDeque<StatusCode>,
+    requests: usize,
+    queries: Vec<Vec<(String, String)>>,
+    write_batches: Vec<usize>,
+    webhooks: Vec<Value>,
+    deleted_webhooks: Vec<String>,
+    payloads: HashMap<String, Vec<Value>>,
+}
+
+// An in-memory stand-in for the Airtable API that serves bases, schemas and paginated records, and can be told to
+// fail upcoming requests.
+#[derive(Clone)]
+pub struct FakeAirtable {
+    state: Arc<Mutex<FakeState>>,
+    page_size: usize,
+}
+
+type SharedState = (Arc<Mutex<FakeState>>, usize);
+
+impl FakeAirtable {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FakeState::default())),
+            page_size: 100,
+        }
+    }
+
+    pub fn with_base(self, base: Base, schema: Schema) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.schemas.insert(base.id.clone(), schema);
+            state.bases.push(base);
+        }
+        self
+    }
+
+    pub fn with_records(self, base_id: &str, table: &str, records: Vec<Record<Value>>) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .records
+            .insert((base_id.to_owned(), table.to_owned()), records);
+        self
+    }
+
+    // Payloads served for a webhook, starting at cursor 1.
+    pub fn with_webhook_payloads(self, webhook_id: &str, payloads: Vec<Value>) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .payloads
+            .insert(webhook_id.to_owned(), payloads);
+        self
+    }
+
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    // Responds to the next request with `status` instead of serving it.
+    pub fn fail_next(&self, status: StatusCode) {
+        self.state.lock().unwrap().failures.push_back(status);
+    }
+
+    pub fn requests(&self) -> usize {
+        self.state.lock().unwrap().requests
+    }
+
+    // The decoded query parameters of every list records request, in order.
+    pub fn queries(&self) -> Vec<Vec<(String, String)>> {
+        self.state.lock().unwrap().queries.clone()
+    }
+
+    // The number of records in each update request, in order.
+    pub fn write_batches(&self) -> Vec<usize> {
+        self.state.lock().unwrap().write_batches.clone()
+    }
+
+    // The body of every create webhook request, in order.
+    pub fn webhooks(&self) -> Vec<Value> {
+        self.state.lock().unwrap().webhooks.clone()
+    }
+
+    pub fn deleted_webhooks(&self) -> Vec<String> {
+        self.state.lock().unwrap().deleted_webhooks.clone()
+    }
+
+    pub fn records(&self, base_id: &str, table: &str) -> Vec<Record<Value>> {
+        self.state
+            .lock()
+            .unwrap()
+            .records
+            .get(&(base_id.to_owned(), table.to_owned()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // Starts serving on a random local port and returns the base uri to pass to `Airtable::new`.
+    pub async fn serve(&self) -> String {
+        let state: SharedState = (self.state.clone(), self.page_size);
+
+        let app = Router::new()
+            .route("/meta/bases", routing::get(list_bases))
+            .route("/meta/bases/:base/tables", routing::get(fetch_schema))
+            .route("/:base/:table", routing::get(list_records).patch(update_records))
+            .route("/bases/:base/webhooks", routing::post(create_webhook))
+            .route("/bases/:base/webhooks/:webhook", routing::delete(delete_webhook))
+            .route(
+                "/bases/:base/webhooks/:webhook/payloads",
+                routing::get(list_webhook_payloads),
+            )
+            .layer(middleware::from_fn_with_state(state.clone(), inject_failures))
+            .with_state(state);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        format!("http://{addr}")
+    }
+}
+
+pub fn record(id: &str, fields: Value) -> Record<Value> {
+    Record {
+        id: id.to_owned(),
+        fields,
+        created_time: "2024-05-01T00:00:00.000Z".to_owned(),
+    }
+}
+
+fn error(status: StatusCode, error_type: &str, message: &str) -> Response {
+    (status, Json(json!({"error": {"type": error_type, "message": message}}))).into_response()
+}
+
+async fn inject_failures(State((state, _)): State<SharedState>, req: Request, next: Next) -> Response {
+    let failure = {
+        let mut state = state.lock().unwrap();
+        state.requests += 1;
+        state.failures.pop_front()
+    };
+
+    match failure {
+        Some(status) => error(status, "INJECTED_ERROR", &format!("injected {status}")),
+        None => next.run(req).await,
+    }
+}
+
+async fn list_bases(
+    State((state, page_size)): State<SharedState>,
+    Query(query): Query<Vec<(String, String)>>,
+) -> Response {
+    let bases = state.lock().unwrap().bases.clone();
+
+    let start = query
+        .iter()
+        .find(|(k, _)| k == "offset")
+        .and_then(|(_, o)| o.parse::<usize>().ok())
+        .unwrap_or(0);
+    let end = (start + page_size).min(bases.len());
+
+    Bases {
+        bases: bases.get(start..end).unwrap_or_default().to_vec(),
+        offset: (end < bases.len()).then(|| end.to_string()),
+    }
+    .into_response()
+}
+
+async fn fetch_schema(State((state, _)): State<SharedState>, Path(base): Path<String>) -> Response {
+    match state.lock().unwrap().schemas.get(&base) {
+        Some(schema) => schema.clone().into_response(),
+        None => (StatusCode::NOT_FOUND, Json(json!({"error": "NOT_FOUND"}))).into_response(),
+    }
+}
+
+async fn list_records(
+    State((state, page_size)): State<SharedState>,
+    Path((base, table)): Path<(String, String)>,
+    Query(query): Query<Vec<(String, String)>>,
+) -> Response {
+    let mut state = state.lock().unwrap();
+    state.queries.push(query.clone());
+
+    let Some(records) = state.records.get(&(base, table.clone())) else {
+        return error(
+            StatusCode::NOT_FOUND,
+            "TABLE_NOT_FOUND",
+            &format!("Could not find table {table}"),
+        );
+    };
+
+    let param = |key: &str| query.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+
+    // only formulas picking records by id are understood, any other formula is ignored
+    let ids = param("filterByFormula")
+        .map(|formula| {
+            formula
+                .split("RECORD_ID()='")
+                .skip(1)
+                .filter_map(|rest| rest.split('\'').next())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let filtered;
+    let records = match ids.is_empty() {
+        true => records,
+        false => {
+            filtered = records
+                .iter()
+                .filter(|r| ids.contains(&r.id.as_str()))
+                .cloned()
+                .collect::<Vec<_>>();
+            &filtered
+        }
+    };
+
+    let start = param("offset").and_then(|o| o.parse::<usize>().ok()).unwrap_or(0);
+    let page_size = param("pageSize")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(page_size);
+    let limit = param("maxRecords")
+        .and_then(|m| m.parse::<usize>().ok())
+        .map_or(records.len(), |m| m.min(records.len()));
+    let end = (start + page_size).min(limit);
+
+    let page = records.get(start..end).unwrap_or_default().to_vec();
+
+    match end < limit {
+        true => Json(json!({"records": page, "offset": end.to_string()})).into_response(),
+        false => Json(json!({ "records": page })).into_response(),
+    }
+}
+
+async fn update_records(
+    State((state, _)): State<SharedState>,
+    Path((base, table)): Path<(String, String)>,
+    Json(body): Json<Value>,
+) -> Response {
+    let mut state = state.lock().unwrap();
+
+    let updates = body["records"].as_array().cloned().unwrap_or_default();
+    if updates.len() > 10 {
+        return error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "INVALID_RECORDS",
+            "at most 10 records can be updated per request",
+        );
+    }
+    state.write_batches.push(updates.len());
+
+    let records = state.records.entry((base, table)).or_default();
+    let mut updated = vec![];
+
+    for update in updates {
+        let id = update["id"].as_str().unwrap_or_default();
+        let Some(record) = records.iter_mut().find(|r| r.id == id) else {
+            return error(
+                StatusCode::NOT_FOUND,
+                "ROW_DOES_NOT_EXIST",
+                &format!("Record {id} does not exist"),
+            );
+        };
+
+        if let (Value::Object(fields), Value::Object(changes)) = (&mut record.fields, &update["fields"]) {
+            fields.extend(changes.clone());
+        }
+        updated.push(record.clone());
+    }
+
+    Json(json!({ "records": updated })).into_response()
+}
+
+async fn create_webhook(State((state, _)): State<SharedState>, Json(body): Json<Value>) -> Response {
+    let mut state = state.lock().unwrap();
+    state.webhooks.push(body);
+
+    Json(json!({
+        "id": format!("achWebhook{}", state.webhooks.len()),
+        "macSecretBase64": "cGFudGhlb24tdGVzdC13ZWJob29rLXNlY3JldA==",
+        "expirationTime": "2024-05-28T09:30:00.000Z",
+    }))
+    .into_response()
+}
+
+async fn delete_webhook(State((state, _)): State<SharedState>, Path((_, webhook)): Path<(String, String)>) -> Response {
+    state.lock().unwrap().deleted_webhooks.push(webhook);
+    StatusCode::OK.into_response()
+}
+
+async fn list_webhook_payloads(
+    State((state, page_size)): State<SharedState>,
+    Path((_, webhook)): Path<(String, String)>,
+    Query(query): Query<Vec<(String, String)>>,
+) -> Response {
+    let state = state.lock().unwrap();
+
+    let Some(payloads) = state.payloads.get(&webhook) else {
+        return (StatusCode::NOT_FOUND, Json(json!({"error": "NOT_FOUND"}))).into_response();
+    };
+
+    let cursor = query
+        .iter()
+        .find(|(k, _)| k == "cursor")
+        .and_then(|(_, c)| c.parse::<usize>().ok())
+        .unwrap_or(1)
+        .max(1);
+    let start = (cursor - 1).min(payloads.len());
+    let end = (start + page_size).min(payloads.len());
+
+    Json(json!({
+        "payloads": &payloads[start..end],
+        "cursor": end + 1,
+        "mightHaveMore": end < payloads.len(),
+    }))
+    .into_response()
+}
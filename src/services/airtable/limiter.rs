@@ -0,0 +1,53 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+// A token bucket per key. Callers reserve a token up front and wait out the deficit, so concurrent callers are
+// queued fairly instead of racing for the next free slot.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64, burst: f64) -> Self {
+        Self {
+            rate: requests_per_second,
+            burst,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn acquire(&self, key: &str) {
+        let wait = {
+            let mut buckets = self.buckets.lock().unwrap();
+            let now = Instant::now();
+
+            let bucket = buckets.entry(key.to_owned()).or_insert(Bucket {
+                tokens: self.burst,
+                updated_at: now,
+            });
+
+            let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst) - 1.0;
+            bucket.updated_at = now;
+
+            match bucket.tokens < 0.0 {
+                true => Duration::from_secs_f64(-bucket.tokens / self.rate),
+                false => Duration::ZERO,
+            }
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -1,23 +1,58 @@
 pub mod bases;
+pub mod errors;
+#[cfg(test)]
+pub mod fake;
+pub mod limiter;
 pub mod record;
 pub mod schema;
+pub mod webhooks;
 
 #[cfg(test)]
 mod tests;
 
 use std::time::Duration;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use derive_builder::Builder;
-use reqwest::Client;
+use rand::Rng;
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 use tokio::time;
 
-use self::{bases::Bases, record::Record, schema::Schema};
+use self::{
+    bases::Bases,
+    errors::AirtableError,
+    limiter::RateLimiter,
+    record::{Record, UpdateRecord},
+    schema::Schema,
+};
 
 pub struct Airtable {
     pub http: Client,
     pub api_token: String,
+    pub base_uri: String,
+    pub limiter: RateLimiter,
+    pub retry_policy: RetryPolicy,
+}
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    // airtable asks clients to wait 30 seconds after being rate limited
+    pub rate_limit_backoff: Duration,
+    pub server_error_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            rate_limit_backoff: Duration::from_secs(30),
+            server_error_backoff: Duration::from_secs(1),
+        }
+    }
 }
 
 #[derive(Builder, Clone, Debug, Serialize, Deserialize)]
@@ -28,6 +63,97 @@ pub struct ListRecordsOptions {
     pub view: Option<String>,
     #[builder(setter(into))]
     pub offset: Option<String>,
+    #[builder(setter(into), default)]
+    pub page_size: Option<u32>,
+    #[builder(setter(into), default)]
+    pub max_records: Option<u32>,
+    #[builder(setter(into), default)]
+    pub filter_by_formula: Option<String>,
+    #[builder(setter(into), default)]
+    pub sort: Option<Vec<SortField>>,
+    #[builder(setter(into), default)]
+    pub cell_format: Option<CellFormat>,
+    #[builder(setter(into), default)]
+    pub time_zone: Option<String>,
+    #[builder(setter(into), default)]
+    pub user_locale: Option<String>,
+    #[builder(setter(into), default)]
+    pub return_fields_by_field_id: Option<bool>,
+    #[builder(setter(into), default)]
+    pub record_metadata: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SortField {
+    pub field: String,
+    pub direction: SortDirection,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CellFormat {
+    Json,
+    String,
+}
+
+impl ListRecordsOptions {
+    // The query parameters for a list records request, left unencoded.
+    pub fn query(&self) -> Vec<(String, String)> {
+        let mut query = vec![];
+
+        for field in self.fields.iter().flatten() {
+            query.push(("fields[]".into(), field.clone()));
+        }
+
+        for (i, sort) in self.sort.iter().flatten().enumerate() {
+            let direction = match sort.direction {
+                SortDirection::Asc => "asc",
+                SortDirection::Desc => "desc",
+            };
+            query.push((format!("sort[{i}][field]"), sort.field.clone()));
+            query.push((format!("sort[{i}][direction]"), direction.into()));
+        }
+
+        for metadata in self.record_metadata.iter().flatten() {
+            query.push(("recordMetadata[]".into(), metadata.clone()));
+        }
+
+        let cell_format = self.cell_format.map(|f| match f {
+            CellFormat::Json => "json".to_owned(),
+            CellFormat::String => "string".to_owned(),
+        });
+
+        let params = [
+            ("view", self.view.clone()),
+            ("offset", self.offset.clone()),
+            ("pageSize", self.page_size.map(|n| n.to_string())),
+            ("maxRecords", self.max_records.map(|n| n.to_string())),
+            ("filterByFormula", self.filter_by_formula.clone()),
+            ("cellFormat", cell_format),
+            ("timeZone", self.time_zone.clone()),
+            ("userLocale", self.user_locale.clone()),
+            (
+                "returnFieldsByFieldId",
+                self.return_fields_by_field_id.map(|b| b.to_string()),
+            ),
+        ];
+
+        for (key, value) in params {
+            if let Some(value) = value {
+                query.push((key.into(), value));
+            }
+        }
+
+        query
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -37,40 +163,108 @@ pub struct ListRecordsResponse<T> {
 }
 
 impl Airtable {
-    const V0_BASE_URI: &'static str = "https://api.airtable.com/v0";
+    const REQUESTS_PER_SECOND_PER_BASE: f64 = 5.0;
+    const META_RATE_LIMIT_KEY: &'static str = "meta";
+    const MAX_RECORDS_PER_WRITE: usize = 10;
 
-    pub fn new(api_token: &str) -> Self {
+    pub fn new(api_token: &str, base_uri: &str) -> Self {
         Self {
             http: Client::new(),
             api_token: api_token.to_owned(),
+            base_uri: base_uri.trim_end_matches('/').to_owned(),
+            limiter: RateLimiter::new(Self::REQUESTS_PER_SECOND_PER_BASE, Self::REQUESTS_PER_SECOND_PER_BASE),
+            retry_policy: RetryPolicy::default(),
         }
     }
 
+    // Sends a request once the base's rate limit allows it, retrying rate limited requests and server errors.
+    async fn send(&self, rate_limit_key: &str, req: RequestBuilder) -> Result<Response> {
+        let mut attempt = 0;
+
+        loop {
+            self.limiter.acquire(rate_limit_key).await;
+
+            let res = req
+                .try_clone()
+                .context("clone airtable request")?
+                .send()
+                .await
+                .context("send airtable request")?;
+
+            let status = res.status();
+            if status.is_success() {
+                return Ok(res);
+            }
+
+            let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+            if !retryable || attempt >= self.retry_policy.max_retries {
+                let body = res.text().await.unwrap_or_default();
+                return Err(AirtableError::from_body(status, &body).into());
+            }
+
+            let backoff = match status {
+                StatusCode::TOO_MANY_REQUESTS => self.retry_policy.rate_limit_backoff,
+                _ => {
+                    let backoff = self.retry_policy.server_error_backoff * 2u32.pow(attempt);
+                    backoff + backoff.mul_f64(rand::thread_rng().gen_range(0.0..0.5))
+                }
+            };
+
+            log::warn!("airtable responded with {status}, retrying in {backoff:?}");
+            time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
+    // Fetches every base the token can access, following offsets until the last page.
     pub async fn list_bases(&self) -> Result<Bases> {
         let http = &self.http;
-        let uri = format!("{}{}", Self::V0_BASE_URI, "/meta/bases");
+        let uri = format!("{}{}", self.base_uri, "/meta/bases");
 
-        let res = http
-            .get(uri)
-            .header("Authorization", format!("Bearer {}", &self.api_token))
-            .send()
-            .await
-            .context("fetch airtable bases")?;
+        let mut bases = vec![];
+        let mut offset: Option<String> = None;
 
-        let bases = res.json::<Bases>().await.context("deserialize airtable bases")?;
+        loop {
+            let req = http
+                .get(&uri)
+                .query(&[("offset", &offset)])
+                .header("Authorization", format!("Bearer {}", &self.api_token));
+
+            let res = self
+                .send(Self::META_RATE_LIMIT_KEY, req)
+                .await
+                .context("fetch airtable bases")?;
+
+            let mut page = res.json::<Bases>().await.context("deserialize airtable bases")?;
+            bases.append(&mut page.bases);
+
+            offset = page.offset;
+            if offset.is_none() {
+                break;
+            }
+        }
 
-        Ok(bases)
+        Ok(Bases { bases, offset: None })
+    }
+
+    // A stable identifier for the api token, used to keep cached data from different tokens apart without storing
+    // the token itself.
+    pub fn cache_namespace(&self) -> String {
+        let digest = format!("{:x}", Sha256::digest(self.api_token.as_bytes()));
+        digest[..16].to_owned()
     }
 
     pub async fn fetch_schema(&self, base_id: &str) -> Result<Schema> {
         let http = &self.http;
 
-        let uri = format!("{}/meta/bases/{}/tables", Self::V0_BASE_URI, base_id);
+        let uri = format!("{}/meta/bases/{}/tables", self.base_uri, base_id);
 
-        let res = http
+        let req = http
             .get(uri)
-            .header("Authorization", format!("Bearer {}", &self.api_token))
-            .send()
+            .header("Authorization", format!("Bearer {}", &self.api_token));
+
+        let res = self
+            .send(base_id, req)
             .await
             .context("error fetching airtable base schema")?;
 
@@ -90,24 +284,17 @@ impl Airtable {
     where
         T: DeserializeOwned + Clone,
     {
-        let mut res = self.list_records::<T>(base_id, table_id_or_name, opts).await?;
-        let mut records = res.records.clone();
-        opts.offset = res.offset.clone();
+        let mut records = vec![];
 
-        let mut length = res.records.len();
+        // airtable only returns an offset while there are more records to fetch
+        loop {
+            let mut res = self.list_records::<T>(base_id, table_id_or_name, opts).await?;
+            records.append(&mut res.records);
+            opts.offset = res.offset;
 
-        log::info!("WE ARE HERE");
-        let mut iteration = 0;
-        while length >= 100 {
-            if iteration % 4 == 0 {
-                time::sleep(Duration::from_secs(1)).await;
+            if opts.offset.is_none() {
+                break;
             }
-
-            res = self.list_records::<T>(base_id, table_id_or_name, opts).await?;
-            length = res.records.len();
-            opts.offset = res.offset.clone();
-            records.append(&mut res.records);
-            iteration += 1;
         }
 
         Ok(records)
@@ -123,35 +310,13 @@ impl Airtable {
         T: DeserializeOwned,
     {
         let http = &self.http;
-        let mut uri = format!("{}/{}/{}?", Self::V0_BASE_URI, base_id, table_id_or_name);
-
-        let fields_query = opts.fields.as_ref().map(|fields| {
-            fields
-                .iter()
-                .map(|f| "&fields%5B%5D=".to_owned() + &f)
-                .fold("".to_owned(), |acc, el| acc + &el)
-        });
+        let uri = self.table_uri(base_id, table_id_or_name)?;
 
         let auth_header = "Bearer ".to_owned() + &self.api_token;
 
-        if let Some(ref query) = fields_query {
-            uri = uri + query;
-        };
-
-        if let Some(ref view) = opts.view {
-            uri = uri + "&view=" + view;
-        };
+        let req = http.get(uri).query(&opts.query()).header("Authorization", auth_header);
 
-        if let Some(ref offset) = opts.offset {
-            uri = uri + "&offset=" + offset;
-        };
-
-        let res = http
-            .get(uri)
-            .header("Authorization", auth_header)
-            .send()
-            .await
-            .context("list airtable records")?;
+        let res = self.send(base_id, req).await.context("list airtable records")?;
 
         let data = res
             .json::<ListRecordsResponse<T>>()
@@ -160,4 +325,47 @@ impl Airtable {
 
         Ok(data)
     }
+
+    // Patches fields onto existing records, in batches of 10 as the airtable api requires.
+    pub async fn update_records<T>(
+        &self,
+        base_id: &str,
+        table_id_or_name: &str,
+        records: &[UpdateRecord<T>],
+    ) -> Result<Vec<Record<Value>>>
+    where
+        T: Serialize,
+    {
+        let http = &self.http;
+        let uri = self.table_uri(base_id, table_id_or_name)?;
+
+        let mut updated = vec![];
+
+        for batch in records.chunks(Self::MAX_RECORDS_PER_WRITE) {
+            let req = http
+                .patch(uri.clone())
+                .header("Authorization", format!("Bearer {}", &self.api_token))
+                .json(&json!({ "records": batch }));
+
+            let res = self.send(base_id, req).await.context("update airtable records")?;
+
+            let mut data = res
+                .json::<ListRecordsResponse<Value>>()
+                .await
+                .context("deserialize updated airtable records")?;
+            updated.append(&mut data.records);
+        }
+
+        Ok(updated)
+    }
+
+    // table names can contain spaces and other characters that have to be escaped in the path
+    fn table_uri(&self, base_id: &str, table_id_or_name: &str) -> Result<Url> {
+        let mut uri = Url::parse(&self.base_uri).context("parse airtable base uri")?;
+        uri.path_segments_mut()
+            .map_err(|_| anyhow!("airtable base uri cannot be a base"))?
+            .push(base_id)
+            .push(table_id_or_name);
+        Ok(uri)
+    }
 }
@@ -7,3 +7,9 @@ pub struct Record<T> {
     pub fields: T,
     pub created_time: String,
 }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UpdateRecord<T> {
+    pub id: String,
+    pub fields: T,
+}
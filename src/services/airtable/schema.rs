@@ -1,3 +1,4 @@
+use anyhow::{bail, Result};
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
@@ -10,11 +11,297 @@ use serde_json::Value;
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Field {
     pub id: String,
-    #[serde(rename = "type")]
-    pub _type: Option<String>,
     pub name: String,
     pub description: Option<String>,
-    pub options: Option<Value>,
+    #[serde(flatten)]
+    pub field_type: FieldType,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Choice {
+    pub id: Option<String>,
+    pub name: String,
+    pub color: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectOptions {
+    pub choices: Vec<Choice>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NumberOptions {
+    pub precision: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CurrencyOptions {
+    pub precision: u8,
+    pub symbol: String,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordLinkOptions {
+    pub linked_table_id: String,
+    pub inverse_link_field_id: Option<String>,
+    pub is_reversed: Option<bool>,
+    pub prefers_single_record_link: Option<bool>,
+    pub view_id_for_record_selection: Option<String>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LookupOptions {
+    pub record_link_field_id: Option<String>,
+    pub field_id_in_linked_table: Option<String>,
+    pub is_valid: Option<bool>,
+    pub result: Option<Box<FieldType>>,
+}
+
+// Shared by formula and rollup fields, whose values take the shape of their result type.
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComputedOptions {
+    pub is_valid: Option<bool>,
+    pub referenced_field_ids: Option<Vec<String>>,
+    pub result: Option<Box<FieldType>>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentOptions {
+    pub is_reversed: Option<bool>,
+}
+
+// An airtable field type with its options. Types that aren't modelled here are kept as `Other` so schemas round-trip
+// without losing anything.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawFieldType", into = "RawFieldType")]
+pub enum FieldType {
+    SingleLineText,
+    MultilineText,
+    RichText,
+    Email,
+    Url,
+    PhoneNumber,
+    Number(NumberOptions),
+    Percent(NumberOptions),
+    Currency(CurrencyOptions),
+    SingleSelect(SelectOptions),
+    MultipleSelects(SelectOptions),
+    MultipleRecordLinks(RecordLinkOptions),
+    MultipleAttachments(AttachmentOptions),
+    Formula(ComputedOptions),
+    Rollup(ComputedOptions),
+    MultipleLookupValues(LookupOptions),
+    Other { field_type: String, options: Option<Value> },
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RawFieldType {
+    #[serde(rename = "type")]
+    field_type: String,
+    options: Option<Value>,
+}
+
+impl From<RawFieldType> for FieldType {
+    fn from(raw: RawFieldType) -> Self {
+        fn options<T: serde::de::DeserializeOwned>(options: &Option<Value>) -> Option<T> {
+            serde_json::from_value(options.clone()?).ok()
+        }
+
+        let field_type = match raw.field_type.as_str() {
+            "singleLineText" => Some(FieldType::SingleLineText),
+            "multilineText" => Some(FieldType::MultilineText),
+            "richText" => Some(FieldType::RichText),
+            "email" => Some(FieldType::Email),
+            "url" => Some(FieldType::Url),
+            "phoneNumber" => Some(FieldType::PhoneNumber),
+            "number" => options(&raw.options).map(FieldType::Number),
+            "percent" => options(&raw.options).map(FieldType::Percent),
+            "currency" => options(&raw.options).map(FieldType::Currency),
+            "singleSelect" => options(&raw.options).map(FieldType::SingleSelect),
+            "multipleSelects" => options(&raw.options).map(FieldType::MultipleSelects),
+            "multipleRecordLinks" => options(&raw.options).map(FieldType::MultipleRecordLinks),
+            "multipleAttachments" => Some(FieldType::MultipleAttachments(
+                options(&raw.options).unwrap_or(AttachmentOptions { is_reversed: None }),
+            )),
+            "formula" => options(&raw.options).map(FieldType::Formula),
+            "rollup" => options(&raw.options).map(FieldType::Rollup),
+            "multipleLookupValues" => options(&raw.options).map(FieldType::MultipleLookupValues),
+            _ => None,
+        };
+
+        // options that don't match the expected shape are kept as they are rather than failing the whole schema
+        field_type.unwrap_or(FieldType::Other {
+            field_type: raw.field_type,
+            options: raw.options,
+        })
+    }
+}
+
+impl From<FieldType> for RawFieldType {
+    fn from(field_type: FieldType) -> Self {
+        fn raw<T: Serialize>(field_type: &str, options: T) -> RawFieldType {
+            RawFieldType {
+                field_type: field_type.to_owned(),
+                options: serde_json::to_value(options).ok().filter(|o| !o.is_null()),
+            }
+        }
+
+        match field_type {
+            FieldType::SingleLineText => raw("singleLineText", None::<()>),
+            FieldType::MultilineText => raw("multilineText", None::<()>),
+            FieldType::RichText => raw("richText", None::<()>),
+            FieldType::Email => raw("email", None::<()>),
+            FieldType::Url => raw("url", None::<()>),
+            FieldType::PhoneNumber => raw("phoneNumber", None::<()>),
+            FieldType::Number(o) => raw("number", o),
+            FieldType::Percent(o) => raw("percent", o),
+            FieldType::Currency(o) => raw("currency", o),
+            FieldType::SingleSelect(o) => raw("singleSelect", o),
+            FieldType::MultipleSelects(o) => raw("multipleSelects", o),
+            FieldType::MultipleRecordLinks(o) => raw("multipleRecordLinks", o),
+            FieldType::MultipleAttachments(o) => raw("multipleAttachments", o),
+            FieldType::Formula(o) => raw("formula", o),
+            FieldType::Rollup(o) => raw("rollup", o),
+            FieldType::MultipleLookupValues(o) => raw("multipleLookupValues", o),
+            FieldType::Other { field_type, options } => RawFieldType { field_type, options },
+        }
+    }
+}
+
+impl FieldType {
+    // The airtable name of the type, e.g. `singleLineText`.
+    pub fn name(&self) -> String {
+        RawFieldType::from(self.clone()).field_type
+    }
+
+    // The type of the values a field holds, which for formulas, rollups and lookups is the type of their result.
+    pub fn value_type(&self) -> &FieldType {
+        let result = match self {
+            FieldType::Formula(o) | FieldType::Rollup(o) => o.result.as_deref(),
+            FieldType::MultipleLookupValues(o) => o.result.as_deref(),
+            _ => None,
+        };
+        result.map_or(self, FieldType::value_type)
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(
+            self.value_type(),
+            FieldType::SingleLineText
+                | FieldType::MultilineText
+                | FieldType::RichText
+                | FieldType::Email
+                | FieldType::Url
+                | FieldType::PhoneNumber
+                | FieldType::SingleSelect(_)
+        )
+    }
+
+    pub fn is_email(&self) -> bool {
+        matches!(self.value_type(), FieldType::Email)
+    }
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: String,
+    pub url: String,
+    pub filename: String,
+    pub size: Option<u64>,
+    #[serde(rename = "type")]
+    pub mime_type: Option<String>,
+}
+
+// A cell decoded according to its field's type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CellValue {
+    Empty,
+    Text(String),
+    Number(f64),
+    Choices(Vec<String>),
+    RecordLinks(Vec<String>),
+    Attachments(Vec<Attachment>),
+    List(Vec<CellValue>),
+    Other(Value),
+}
+
+impl CellValue {
+    pub fn decode(field_type: &FieldType, value: &Value) -> Result<Self> {
+        fn strings(values: &[Value]) -> Result<Vec<String>> {
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::String(s) => Ok(s.clone()),
+                    _ => bail!("expected a string, got {v}"),
+                })
+                .collect()
+        }
+
+        let cell = match (field_type, value) {
+            (_, Value::Null) => CellValue::Empty,
+            (
+                FieldType::SingleLineText
+                | FieldType::MultilineText
+                | FieldType::RichText
+                | FieldType::Email
+                | FieldType::Url
+                | FieldType::PhoneNumber
+                | FieldType::SingleSelect(_),
+                Value::String(s),
+            ) => CellValue::Text(s.clone()),
+            (FieldType::Number(_) | FieldType::Percent(_) | FieldType::Currency(_), Value::Number(n)) => {
+                CellValue::Number(n.as_f64().unwrap_or_default())
+            }
+            (FieldType::MultipleSelects(_), Value::Array(values)) => CellValue::Choices(strings(values)?),
+            (FieldType::MultipleRecordLinks(_), Value::Array(values)) => CellValue::RecordLinks(strings(values)?),
+            (FieldType::MultipleAttachments(_), Value::Array(_)) => {
+                CellValue::Attachments(serde_json::from_value(value.clone())?)
+            }
+            // formulas that fail to evaluate come back as `{"error": "#ERROR!"}`, which is kept as is
+            (FieldType::Formula(o) | FieldType::Rollup(o), _) => match o.result.as_deref() {
+                Some(result) => Self::decode(result, value).unwrap_or_else(|_| CellValue::Other(value.clone())),
+                None => CellValue::Other(value.clone()),
+            },
+            (FieldType::MultipleLookupValues(o), Value::Array(values)) => CellValue::List(
+                values
+                    .iter()
+                    .map(|v| match o.result.as_deref() {
+                        Some(result) => Self::decode(result, v),
+                        None => Ok(CellValue::Other(v.clone())),
+                    })
+                    .collect::<Result<Vec<CellValue>>>()?,
+            ),
+            (FieldType::Other { .. }, _) => CellValue::Other(value.clone()),
+            (field_type, value) => bail!("unexpected value {value} for a {} field", field_type.name()),
+        };
+
+        Ok(cell)
+    }
+
+    // The cell as plain text, using the first value of lists and lookups.
+    pub fn as_text(&self) -> Option<String> {
+        match self {
+            CellValue::Text(s) => Some(s.clone()),
+            CellValue::Number(n) => Some(n.to_string()),
+            CellValue::Choices(choices) => Some(choices.join(", ")),
+            CellValue::List(values) => values.first().and_then(CellValue::as_text),
+            _ => None,
+        }
+    }
 }
 
 #[serde_with::skip_serializing_none]
@@ -40,11 +327,58 @@ pub struct Table {
     pub views: Vec<View>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnKind {
+    Text,
+    Email,
+}
+
+impl Table {
+    pub fn field(&self, id_or_name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.id == id_or_name || f.name == id_or_name)
+    }
+
+    pub fn view(&self, id_or_name: &str) -> Option<&View> {
+        self.views.iter().find(|v| v.id == id_or_name || v.name == id_or_name)
+    }
+
+    // Checks that a mapped column exists and holds the kind of value it is mapped as.
+    pub fn validate_column(&self, column: &str, kind: ColumnKind) -> Result<&Field> {
+        let Some(field) = self.field(column) else {
+            bail!("table {} has no field {column}", self.name);
+        };
+
+        let matches = match kind {
+            ColumnKind::Text => field.field_type.is_text(),
+            ColumnKind::Email => field.field_type.is_email(),
+        };
+
+        if !matches {
+            bail!(
+                "field {column} is a {} field, expected {}",
+                field.field_type.value_type().name(),
+                match kind {
+                    ColumnKind::Text => "a text field",
+                    ColumnKind::Email => "an email field",
+                }
+            );
+        }
+
+        Ok(field)
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Schema {
     pub tables: Vec<Table>,
 }
 
+impl Schema {
+    pub fn table(&self, id_or_name: &str) -> Option<&Table> {
+        self.tables.iter().find(|t| t.id == id_or_name || t.name == id_or_name)
+    }
+}
+
 impl IntoResponse for Schema {
     fn into_response(self) -> Response {
         (StatusCode::OK, Json(self)).into_response()
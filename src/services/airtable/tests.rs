@@ -1,43 +1,519 @@
-use std::env;
+use std::time::{Duration, Instant};
 
-use serde::{Deserialize, Serialize};
+use axum::http::StatusCode;
+use serde_json::{json, Value};
 
-use crate::services::airtable::{Airtable, ListRecordsOptions};
+use crate::services::airtable::{
+    bases::Base,
+    errors::AirtableError,
+    fake::{record, FakeAirtable},
+    limiter::RateLimiter,
+    record::{Record, UpdateRecord},
+    schema::{CellValue, ColumnKind, FieldType, Schema, Table},
+    webhooks::{verify_signature, WebhookNotification},
+    Airtable, CellFormat, ListRecordsOptions, ListRecordsOptionsBuilder, RetryPolicy, SortDirection, SortField,
+};
+
+fn records(n: usize) -> Vec<Record<Value>> {
+    (0..n)
+        .map(|i| record(&format!("rec{i}"), json!({"Name": format!("record {i}")})))
+        .collect()
+}
+
+fn options() -> ListRecordsOptions {
+    ListRecordsOptionsBuilder::default()
+        .view(None)
+        .fields(None)
+        .offset(None)
+        .build()
+        .unwrap()
+}
+
+fn fast_retries(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        rate_limit_backoff: Duration::from_millis(10),
+        server_error_backoff: Duration::from_millis(1),
+    }
+}
 
 #[tokio::test]
-pub async fn test_list_records() {
-    dotenvy::dotenv().expect("error loading environment variables");
+pub async fn test_list_bases_and_fetch_schema() {
+    let base = Base {
+        id: "app1".into(),
+        name: "Volunteers".into(),
+        permission_level: "create".into(),
+    };
+    let schema = Schema {
+        tables: vec![Table {
+            id: "tbl1".into(),
+            primary_field_id: "fld1".into(),
+            name: "People".into(),
+            description: None,
+            fields: vec![],
+            views: vec![],
+        }],
+    };
+
+    let fake = FakeAirtable::new().with_base(base, schema);
+    let airtable = Airtable::new("token", &fake.serve().await);
+
+    let bases = airtable.list_bases().await.unwrap();
+    assert_eq!(bases.bases.len(), 1);
+    assert_eq!(bases.bases[0].id, "app1");
+
+    let schema = airtable.fetch_schema("app1").await.unwrap();
+    assert_eq!(schema.tables[0].name, "People");
+
+    let err = airtable.fetch_schema("app2").await.unwrap_err();
+    assert_eq!(err.downcast_ref::<AirtableError>().unwrap().error_type, "NOT_FOUND");
+}
+
+#[tokio::test]
+pub async fn test_list_bases_follows_offset() {
+    let fake = (0..5).fold(FakeAirtable::new().with_page_size(2), |fake, i| {
+        let base = Base {
+            id: format!("app{i}"),
+            name: format!("Base {i}"),
+            permission_level: "read".into(),
+        };
+        fake.with_base(base, Schema { tables: vec![] })
+    });
+    let airtable = Airtable::new("token", &fake.serve().await);
+
+    let bases = airtable.list_bases().await.unwrap();
 
-    let airtable = Airtable::new(&env::var("AIRTABLE_API_TOKEN").expect("missing airtable api token"));
+    assert_eq!(bases.bases.len(), 5);
+    assert_eq!(bases.bases[4].id, "app4");
+    assert!(bases.offset.is_none());
+    assert_eq!(fake.requests(), 3);
+}
+
+#[test]
+pub fn test_cache_namespace_depends_on_token() {
+    let first = Airtable::new("first-token", "http://localhost");
+    let second = Airtable::new("second-token", "http://localhost");
 
-    let (airtable_base, airtable_table, airtable_view) = (
-        env::var("TEST_AIRTABLE_BASE").expect("missing test airtable base env variable"),
-        env::var("TEST_AIRTABLE_TABLE").expect("missing test airtable table variable"),
-        env::var("TEST_AIRTABLE_VIEW").expect("missing test airtable view variable"),
+    assert_eq!(
+        first.cache_namespace(),
+        Airtable::new("first-token", "http://other").cache_namespace()
     );
+    assert_ne!(first.cache_namespace(), second.cache_namespace());
+    assert!(!first.cache_namespace().contains("first-token"));
+}
+
+#[tokio::test]
+pub async fn test_list_all_records_follows_offset() {
+    let fake = FakeAirtable::new().with_records("base", "table", records(250));
+    let airtable = Airtable::new("token", &fake.serve().await);
 
-    dbg!(&airtable.api_token);
+    let records = airtable
+        .list_all_records::<Value>("base", "table", &mut options())
+        .await
+        .unwrap();
 
-    #[derive(Debug, Serialize, Deserialize)]
-    #[serde(rename_all = "PascalCase")]
-    struct TestDataStruct {
-        first_name: String,
-        last_name: String,
-        email: String,
-    }
+    assert_eq!(records.len(), 250);
+    assert_eq!(records.last().unwrap().id, "rec249");
+    assert_eq!(fake.requests(), 3);
+}
+
+#[tokio::test]
+pub async fn test_list_all_records_stops_without_offset() {
+    let fake = FakeAirtable::new().with_records("base", "table", records(100));
+    let airtable = Airtable::new("token", &fake.serve().await);
+
+    let records = airtable
+        .list_all_records::<Value>("base", "table", &mut options())
+        .await
+        .unwrap();
+
+    assert_eq!(records.len(), 100);
+    assert_eq!(fake.requests(), 1);
+}
+
+#[tokio::test]
+pub async fn test_list_all_records_page_size_and_max_records() {
+    let fake = FakeAirtable::new().with_records("base", "table", records(250));
+    let airtable = Airtable::new("token", &fake.serve().await);
+
+    let mut opts = ListRecordsOptionsBuilder::default()
+        .view(None)
+        .fields(None)
+        .offset(None)
+        .page_size(20)
+        .max_records(70)
+        .build()
+        .unwrap();
 
     let records = airtable
-        .list_records::<TestDataStruct>(
-            &airtable_base,
-            &airtable_table,
-            &ListRecordsOptions {
-                view: Some(airtable_view),
-                fields: Some(vec!["FirstName".into(), "LastName".into(), "Email".into()]),
-                offset: None,
-            },
+        .list_all_records::<Value>("base", "table", &mut opts)
+        .await
+        .unwrap();
+
+    assert_eq!(records.len(), 70);
+    assert_eq!(fake.requests(), 4);
+}
+
+#[tokio::test]
+pub async fn test_list_records_retries_rate_limits_and_server_errors() {
+    let fake = FakeAirtable::new().with_records("base", "table", records(3));
+    fake.fail_next(StatusCode::TOO_MANY_REQUESTS);
+    fake.fail_next(StatusCode::SERVICE_UNAVAILABLE);
+
+    let mut airtable = Airtable::new("token", &fake.serve().await);
+    airtable.retry_policy = fast_retries(2);
+
+    let res = airtable
+        .list_records::<Value>("base", "table", &options())
+        .await
+        .unwrap();
+
+    assert_eq!(res.records.len(), 3);
+    assert_eq!(fake.requests(), 3);
+}
+
+#[tokio::test]
+pub async fn test_list_records_returns_typed_errors() {
+    let fake = FakeAirtable::new().with_records("base", "table", records(3));
+    for _ in 0..3 {
+        fake.fail_next(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let mut airtable = Airtable::new("token", &fake.serve().await);
+    airtable.retry_policy = fast_retries(1);
+
+    let err = airtable
+        .list_records::<Value>("base", "table", &options())
+        .await
+        .unwrap_err();
+    let err = err.downcast_ref::<AirtableError>().unwrap();
+
+    assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(err.error_type, "INJECTED_ERROR");
+    assert_eq!(fake.requests(), 2);
+
+    let err = airtable
+        .list_records::<Value>("base", "missing", &options())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<AirtableError>().unwrap().error_type,
+        "TABLE_NOT_FOUND"
+    );
+}
+
+#[tokio::test]
+pub async fn test_list_records_encodes_query() {
+    let fake = FakeAirtable::new().with_records("app123", "Volunteers & Mentors", records(1));
+    let airtable = Airtable::new("token", &fake.serve().await);
+
+    let opts = ListRecordsOptionsBuilder::default()
+        .view(Some("Grid view #2".into()))
+        .fields(Some(vec!["First Name".into()]))
+        .offset(None)
+        .filter_by_formula(Some("AND({Status} = 'Active', {Age} >= 18)".into()))
+        .sort(Some(vec![SortField {
+            field: "Last Name".into(),
+            direction: SortDirection::Desc,
+        }]))
+        .cell_format(Some(CellFormat::String))
+        .time_zone(Some("America/New_York".into()))
+        .user_locale(Some("en-us".into()))
+        .build()
+        .unwrap();
+
+    let res = airtable
+        .list_records::<Value>("app123", "Volunteers & Mentors", &opts)
+        .await
+        .unwrap();
+
+    assert_eq!(res.records.len(), 1);
+    assert_eq!(
+        fake.queries(),
+        vec![vec![
+            ("fields[]".to_owned(), "First Name".to_owned()),
+            ("sort[0][field]".to_owned(), "Last Name".to_owned()),
+            ("sort[0][direction]".to_owned(), "desc".to_owned()),
+            ("view".to_owned(), "Grid view #2".to_owned()),
+            (
+                "filterByFormula".to_owned(),
+                "AND({Status} = 'Active', {Age} >= 18)".to_owned()
+            ),
+            ("cellFormat".to_owned(), "string".to_owned()),
+            ("timeZone".to_owned(), "America/New_York".to_owned()),
+            ("userLocale".to_owned(), "en-us".to_owned()),
+        ]]
+    );
+}
+
+#[tokio::test]
+pub async fn test_update_records_in_batches_of_ten() {
+    let fake = FakeAirtable::new().with_records("base", "table", records(23));
+    let airtable = Airtable::new("token", &fake.serve().await);
+
+    let updates = (0..23)
+        .map(|i| UpdateRecord {
+            id: format!("rec{i}"),
+            fields: json!({"Workspace Email": format!("user{i}@developforgood.org")}),
+        })
+        .collect::<Vec<UpdateRecord<Value>>>();
+
+    let updated = airtable.update_records("base", "table", &updates).await.unwrap();
+
+    assert_eq!(updated.len(), 23);
+    assert_eq!(fake.write_batches(), vec![10, 10, 3]);
+
+    let stored = fake.records("base", "table");
+    assert_eq!(stored[22].fields["Workspace Email"], "user22@developforgood.org");
+    assert_eq!(stored[22].fields["Name"], "record 22");
+}
+
+#[tokio::test]
+pub async fn test_rate_limiter_spaces_requests_per_key() {
+    let limiter = RateLimiter::new(20.0, 2.0);
+    let start = Instant::now();
+
+    for _ in 0..4 {
+        limiter.acquire("base").await;
+    }
+    limiter.acquire("other").await;
+
+    // two requests fit in the burst and the other two wait 50ms each
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(95), "{elapsed:?}");
+    assert!(elapsed < Duration::from_millis(500), "{elapsed:?}");
+}
+
+#[test]
+pub fn test_airtable_error_from_body() {
+    let err = AirtableError::from_body(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        r#"{"error": {"type": "INVALID_FILTER_BY_FORMULA", "message": "The formula is invalid"}}"#,
+    );
+    assert_eq!(err.error_type, "INVALID_FILTER_BY_FORMULA");
+    assert_eq!(err.message, "The formula is invalid");
+
+    let err = AirtableError::from_body(StatusCode::NOT_FOUND, r#"{"error": "NOT_FOUND"}"#);
+    assert_eq!(err.error_type, "NOT_FOUND");
+
+    let err = AirtableError::from_body(StatusCode::BAD_GATEWAY, "upstream unavailable");
+    assert_eq!(err.error_type, "502");
+    assert_eq!(err.message, "upstream unavailable");
+}
+
+#[test]
+pub fn test_list_records_options_query() {
+    let opts = ListRecordsOptionsBuilder::default()
+        .view(Some("Grid view".into()))
+        .fields(Some(vec!["First Name".into(), "Email".into()]))
+        .offset(Some("itr1/rec2".into()))
+        .page_size(50)
+        .sort(Some(vec![SortField {
+            field: "Last Name".into(),
+            direction: SortDirection::Asc,
+        }]))
+        .cell_format(Some(CellFormat::Json))
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        opts.query(),
+        vec![
+            ("fields[]".to_owned(), "First Name".to_owned()),
+            ("fields[]".to_owned(), "Email".to_owned()),
+            ("sort[0][field]".to_owned(), "Last Name".to_owned()),
+            ("sort[0][direction]".to_owned(), "asc".to_owned()),
+            ("view".to_owned(), "Grid view".to_owned()),
+            ("offset".to_owned(), "itr1/rec2".to_owned()),
+            ("pageSize".to_owned(), "50".to_owned()),
+            ("cellFormat".to_owned(), "json".to_owned()),
+        ]
+    );
+}
+
+fn people_schema() -> Schema {
+    serde_json::from_value(json!({
+        "tables": [{
+            "id": "tbl1",
+            "primaryFieldId": "fld1",
+            "name": "People",
+            "fields": [
+                {"id": "fld1", "name": "Name", "type": "singleLineText"},
+                {"id": "fld2", "name": "Email", "type": "email"},
+                {"id": "fld3", "name": "Skills", "type": "multipleSelects", "options": {"choices": [
+                    {"id": "sel1", "name": "Rust", "color": "blueLight2"},
+                    {"id": "sel2", "name": "Design", "color": "redLight2"}
+                ]}},
+                {"id": "fld4", "name": "Project", "type": "multipleRecordLinks", "options": {
+                    "linkedTableId": "tbl2", "isReversed": false, "prefersSingleRecordLink": true
+                }},
+                {"id": "fld5", "name": "Project Email", "type": "multipleLookupValues", "options": {
+                    "isValid": true, "recordLinkFieldId": "fld4", "fieldIdInLinkedTable": "fld9",
+                    "result": {"type": "email"}
+                }},
+                {"id": "fld6", "name": "Hours", "type": "formula", "options": {
+                    "isValid": true, "referencedFieldIds": [], "result": {"type": "number", "options": {"precision": 1}}
+                }},
+                {"id": "fld7", "name": "Photo", "type": "multipleAttachments", "options": {"isReversed": false}},
+                {"id": "fld8", "name": "Joined", "type": "date", "options": {"dateFormat": {"name": "iso", "format": "YYYY-MM-DD"}}}
+            ],
+            "views": [{"id": "viw1", "name": "Grid view", "type": "grid"}]
+        }]
+    }))
+    .unwrap()
+}
+
+#[test]
+pub fn test_schema_field_types_round_trip() {
+    let schema = people_schema();
+    let table = schema.table("People").unwrap();
+
+    assert_eq!(table.field("fld2").unwrap().field_type, FieldType::Email);
+    assert!(matches!(
+        &table.field("Project").unwrap().field_type,
+        FieldType::MultipleRecordLinks(o) if o.linked_table_id == "tbl2"
+    ));
+    assert!(table.field("Project Email").unwrap().field_type.is_email());
+    assert!(matches!(
+        &table.field("Joined").unwrap().field_type,
+        FieldType::Other { field_type, .. } if field_type == "date"
+    ));
+
+    let value = serde_json::to_value(&schema).unwrap();
+    assert_eq!(
+        value["tables"][0]["fields"][0],
+        json!({"id": "fld1", "name": "Name", "type": "singleLineText"})
+    );
+    assert_eq!(value["tables"][0]["fields"][7]["options"]["dateFormat"]["name"], "iso");
+    assert_eq!(
+        value["tables"][0]["fields"][2]["options"]["choices"][1]["name"],
+        "Design"
+    );
+}
+
+#[test]
+pub fn test_decode_cell_values() {
+    let schema = people_schema();
+    let table = schema.table("tbl1").unwrap();
+    let decode = |field: &str, value: Value| CellValue::decode(&table.field(field).unwrap().field_type, &value);
+
+    assert_eq!(decode("Name", json!("Ada")).unwrap(), CellValue::Text("Ada".into()));
+    assert_eq!(decode("Name", Value::Null).unwrap(), CellValue::Empty);
+    assert_eq!(
+        decode("Skills", json!(["Rust", "Design"])).unwrap(),
+        CellValue::Choices(vec!["Rust".into(), "Design".into()])
+    );
+    assert_eq!(
+        decode("Project", json!(["rec9"])).unwrap(),
+        CellValue::RecordLinks(vec!["rec9".into()])
+    );
+    assert_eq!(
+        decode("Project Email", json!(["team@example.com"])).unwrap().as_text(),
+        Some("team@example.com".into())
+    );
+    assert_eq!(decode("Hours", json!(12.5)).unwrap(), CellValue::Number(12.5));
+    assert_eq!(
+        decode("Hours", json!({"error": "#ERROR!"})).unwrap(),
+        CellValue::Other(json!({"error": "#ERROR!"}))
+    );
+
+    let photo = decode(
+        "Photo",
+        json!([{"id": "att1", "url": "https://example.com/a.png", "filename": "a.png", "size": 10, "type": "image/png"}]),
+    )
+    .unwrap();
+    assert!(matches!(photo, CellValue::Attachments(a) if a[0].filename == "a.png"));
+
+    assert!(decode("Name", json!(["not", "text"])).is_err());
+}
+
+#[test]
+pub fn test_validate_columns() {
+    let schema = people_schema();
+    let table = schema.table("People").unwrap();
+
+    assert!(table.validate_column("Name", ColumnKind::Text).is_ok());
+    assert!(table.validate_column("Email", ColumnKind::Email).is_ok());
+    assert!(table.validate_column("Project Email", ColumnKind::Email).is_ok());
+    assert!(table.validate_column("Name", ColumnKind::Email).is_err());
+    assert!(table.validate_column("Skills", ColumnKind::Text).is_err());
+    assert!(table.validate_column("Missing", ColumnKind::Text).is_err());
+}
+
+const WEBHOOK_SECRET: &str = "cGFudGhlb24tdGVzdC13ZWJob29rLXNlY3JldA==";
+const NOTIFICATION: &str =
+    r#"{"base":{"id":"appPeople"},"webhook":{"id":"achWebhook1"},"timestamp":"2024-05-21T09:30:00.000Z"}"#;
+const NOTIFICATION_MAC: &str = "hmac-sha256=63c518069e76137e7a19e4ad965b42f0fbc87ff43af24d9dc01e1ec97fe727d6";
+
+#[test]
+pub fn test_verify_webhook_signature() {
+    let notification = serde_json::from_str::<WebhookNotification>(NOTIFICATION).unwrap();
+    assert_eq!(notification.webhook.id, "achWebhook1");
+
+    assert!(verify_signature(
+        WEBHOOK_SECRET,
+        NOTIFICATION.as_bytes(),
+        NOTIFICATION_MAC
+    ));
+
+    let tampered = NOTIFICATION.replace("achWebhook1", "achWebhook2");
+    assert!(!verify_signature(WEBHOOK_SECRET, tampered.as_bytes(), NOTIFICATION_MAC));
+    assert!(!verify_signature(
+        WEBHOOK_SECRET,
+        NOTIFICATION.as_bytes(),
+        &NOTIFICATION_MAC[12..]
+    ));
+    assert!(!verify_signature(
+        "not base64!",
+        NOTIFICATION.as_bytes(),
+        NOTIFICATION_MAC
+    ));
+}
+
+#[tokio::test]
+pub async fn test_create_webhook_and_list_payloads() {
+    let payloads = (1..=3)
+        .map(|n| json!({"timestamp": "2024-05-21T09:30:00.000Z", "baseTransactionNumber": n}))
+        .collect();
+
+    let fake = FakeAirtable::new()
+        .with_page_size(2)
+        .with_webhook_payloads("achWebhook1", payloads);
+    let airtable = Airtable::new("token", &fake.serve().await);
+
+    let fields = vec!["fldFirst".to_owned(), "fldEmail".to_owned()];
+    let webhook = airtable
+        .create_webhook(
+            "appPeople",
+            "https://pantheon.example.com/webhooks/airtable",
+            "viwGrid",
+            &fields,
         )
         .await
-        .expect("error fetching records");
+        .unwrap();
+
+    assert_eq!(webhook.id, "achWebhook1");
+    assert_eq!(webhook.mac_secret_base64, WEBHOOK_SECRET);
+
+    let spec = &fake.webhooks()[0]["specification"]["options"];
+    assert_eq!(spec["filters"]["recordChangeScope"], "viwGrid");
+    assert_eq!(spec["includes"]["includeCellValuesInFieldIds"], json!(fields));
+
+    let page = airtable
+        .list_webhook_payloads("appPeople", "achWebhook1", 1)
+        .await
+        .unwrap();
+    assert_eq!(page.payloads.len(), 2);
+    assert!(page.might_have_more);
+
+    let page = airtable
+        .list_webhook_payloads("appPeople", "achWebhook1", page.cursor)
+        .await
+        .unwrap();
+    assert_eq!(page.payloads[0].base_transaction_number, 3);
+    assert_eq!(page.cursor, 4);
+    assert!(!page.might_have_more);
 
-    dbg!(&records);
+    airtable.delete_webhook("appPeople", "achWebhook1").await.unwrap();
+    assert_eq!(fake.deleted_webhooks(), vec!["achWebhook1"]);
 }
@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::Sha256;
+
+use super::Airtable;
+
+// The body airtable posts to a webhook's notification url. It only says that new payloads are available, which then
+// have to be fetched with `list_webhook_payloads`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WebhookNotification {
+    pub base: WebhookRef,
+    pub webhook: WebhookRef,
+    pub timestamp: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WebhookRef {
+    pub id: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedWebhook {
+    pub id: String,
+    pub mac_secret_base64: String,
+    pub expiration_time: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshedWebhook {
+    pub expiration_time: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookPayloads {
+    pub payloads: Vec<WebhookPayload>,
+    pub cursor: i64,
+    pub might_have_more: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookPayload {
+    pub timestamp: String,
+    pub base_transaction_number: i64,
+    #[serde(default)]
+    pub changed_tables_by_id: HashMap<String, TableChanges>,
+}
+
+// The record changes to one table in a payload. Field and metadata changes are left out.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TableChanges {
+    pub created_records_by_id: BTreeMap<String, CreatedRecord>,
+    pub changed_records_by_id: BTreeMap<String, ChangedRecord>,
+    pub destroyed_record_ids: Vec<String>,
+    pub changed_views_by_id: BTreeMap<String, ViewChanges>,
+}
+
+// The records that entered, changed in or left one view of a table. Records that leave a view without being
+// destroyed only show up here.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ViewChanges {
+    pub created_records_by_id: BTreeMap<String, CreatedRecord>,
+    pub changed_records_by_id: BTreeMap<String, ChangedRecord>,
+    pub destroyed_record_ids: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedRecord {
+    pub created_time: String,
+    #[serde(default)]
+    pub cell_values_by_field_id: Map<String, Value>,
+}
+
+// `current` holds the cells that changed, `unchanged` the other fields the webhook includes cell values for.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChangedRecord {
+    pub current: CellValues,
+    #[serde(default)]
+    pub unchanged: CellValues,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CellValues {
+    pub cell_values_by_field_id: Map<String, Value>,
+}
+
+// Checks the `X-Airtable-Content-MAC` header of a notification, an hmac-sha256 of the raw body keyed with the
+// webhook's decoded mac secret.
+pub fn verify_signature(mac_secret_base64: &str, body: &[u8], header: &str) -> bool {
+    let (Ok(secret), Some(Ok(signature))) = (
+        STANDARD.decode(mac_secret_base64),
+        header.strip_prefix("hmac-sha256=").map(hex::decode),
+    ) else {
+        return false;
+    };
+
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(&secret) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+impl Airtable {
+    // Registers a webhook for record changes in a table or view, including the current values of `field_ids` in
+    // every change so records can be updated without fetching them again.
+    pub async fn create_webhook(
+        &self,
+        base_id: &str,
+        notification_url: &str,
+        record_change_scope: &str,
+        field_ids: &[String],
+    ) -> Result<CreatedWebhook> {
+        let uri = format!("{}/bases/{}/webhooks", self.base_uri, base_id);
+
+        let body = json!({
+            "notificationUrl": notification_url,
+            "specification": {
+                "options": {
+                    "filters": {
+                        "dataTypes": ["tableData"],
+                        "recordChangeScope": record_change_scope,
+                        "watchDataInFieldIds": field_ids,
+                    },
+                    "includes": {
+                        "includeCellValuesInFieldIds": field_ids,
+                    },
+                },
+            },
+        });
+
+        let req = self
+            .http
+            .post(uri)
+            .header("Authorization", format!("Bearer {}", &self.api_token))
+            .json(&body);
+
+        let res = self.send(base_id, req).await.context("create airtable webhook")?;
+
+        res.json::<CreatedWebhook>()
+            .await
+            .context("deserialize created airtable webhook")
+    }
+
+    pub async fn list_webhook_payloads(&self, base_id: &str, webhook_id: &str, cursor: i64) -> Result<WebhookPayloads> {
+        let uri = format!("{}/bases/{}/webhooks/{}/payloads", self.base_uri, base_id, webhook_id);
+
+        let req = self
+            .http
+            .get(uri)
+            .query(&[("cursor", cursor)])
+            .header("Authorization", format!("Bearer {}", &self.api_token));
+
+        let res = self
+            .send(base_id, req)
+            .await
+            .context("list airtable webhook payloads")?;
+
+        res.json::<WebhookPayloads>()
+            .await
+            .context("deserialize airtable webhook payloads")
+    }
+
+    pub async fn delete_webhook(&self, base_id: &str, webhook_id: &str) -> Result<()> {
+        let uri = format!("{}/bases/{}/webhooks/{}", self.base_uri, base_id, webhook_id);
+
+        let req = self
+            .http
+            .delete(uri)
+            .header("Authorization", format!("Bearer {}", &self.api_token));
+
+        self.send(base_id, req).await.context("delete airtable webhook")?;
+
+        Ok(())
+    }
+
+    // Webhooks expire seven days after they are created or last refreshed.
+    pub async fn refresh_webhook(&self, base_id: &str, webhook_id: &str) -> Result<RefreshedWebhook> {
+        let uri = format!("{}/bases/{}/webhooks/{}/refresh", self.base_uri, base_id, webhook_id);
+
+        let req = self
+            .http
+            .post(uri)
+            .header("Authorization", format!("Bearer {}", &self.api_token));
+
+        let res = self.send(base_id, req).await.context("refresh airtable webhook")?;
+
+        res.json::<RefreshedWebhook>()
+            .await
+            .context("deserialize refreshed airtable webhook")
+    }
+}
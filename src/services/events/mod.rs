@@ -0,0 +1,116 @@
+use anyhow::Result;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use super::storage::Cache;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum JobEventKind {
+    #[serde(rename_all = "camelCase")]
+    Started {
+        total_items: Option<i32>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        processed_items: i32,
+        total_items: Option<i32>,
+    },
+    Complete,
+    Errored {
+        error: String,
+    },
+}
+
+impl JobEventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Started { .. } => "started",
+            Self::Progress { .. } => "progress",
+            Self::Complete => "complete",
+            Self::Errored { .. } => "errored",
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Complete | Self::Errored { .. })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobEvent {
+    pub job_id: Uuid,
+    pub datasource_view_id: Option<Uuid>,
+    #[serde(flatten)]
+    pub kind: JobEventKind,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    origin: Uuid,
+    event: JobEvent,
+}
+
+pub struct JobEvents {
+    sender: broadcast::Sender<JobEvent>,
+    instance_id: Uuid,
+    fanout: bool,
+}
+
+impl JobEvents {
+    const CHANNEL_CAPACITY: usize = 256;
+    const REDIS_CHANNEL: &'static str = "pantheon:job-events";
+
+    pub fn new(fanout: bool) -> Self {
+        let (sender, _) = broadcast::channel(Self::CHANNEL_CAPACITY);
+        Self {
+            sender,
+            instance_id: Uuid::new_v4(),
+            fanout,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.sender.subscribe()
+    }
+
+    pub async fn publish(&self, cache: &Cache, event: JobEvent) {
+        if self.fanout {
+            let envelope = Envelope {
+                origin: self.instance_id,
+                event: event.clone(),
+            };
+            if let Err(e) = cache.publish_json(Self::REDIS_CHANNEL, &envelope).await {
+                log::warn!("unable to fan out job event: {e}");
+            }
+        }
+
+        // sending only fails when nobody is listening, which is fine
+        let _ = self.sender.send(event);
+    }
+
+    // forwards events published by other instances to local subscribers
+    pub async fn relay(&self, cache: &Cache) -> Result<()> {
+        let mut pubsub = cache.subscribe(Self::REDIS_CHANNEL).await?;
+        let mut messages = pubsub.on_message();
+
+        while let Some(msg) = messages.next().await {
+            let envelope = match serde_json::from_slice::<Envelope>(msg.get_payload_bytes()) {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    log::warn!("dropping malformed job event: {e}");
+                    continue;
+                }
+            };
+
+            if envelope.origin != self.instance_id {
+                let _ = self.sender.send(envelope.event);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
 pub mod airtable;
 pub mod auth;
+pub mod events;
 pub mod storage;
+pub mod uploads;
 pub mod workspace;
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use mobc::Pool;
-use mobc_redis::redis::AsyncCommands;
+use mobc_redis::redis::{aio::PubSub, AsyncCommands};
 use mobc_redis::{redis, RedisConnectionManager};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::time::Duration;
@@ -9,6 +9,7 @@ pub type RedisPool = Pool<RedisConnectionManager>;
 
 pub struct Cache {
     pub redis: RedisPool,
+    pub client: redis::Client,
 }
 
 const CACHE_POOL_MAX_OPEN: u64 = 16;
@@ -19,14 +20,14 @@ const CACHE_POOL_EXPIRE_SECONDS: u64 = 60;
 impl Cache {
     pub fn new(cache_url: &str) -> Result<Self> {
         let client = redis::Client::open(cache_url)?;
-        let manager = RedisConnectionManager::new(client);
+        let manager = RedisConnectionManager::new(client.clone());
         let pool = Pool::builder()
             .get_timeout(Some(Duration::from_secs(CACHE_POOL_TIMEOUT_SECONDS)))
             .max_open(CACHE_POOL_MAX_OPEN)
             .max_idle(CACHE_POOL_MAX_IDLE)
             .max_lifetime(Some(Duration::from_secs(CACHE_POOL_EXPIRE_SECONDS)))
             .build(manager);
-        Ok(Cache { redis: pool })
+        Ok(Cache { redis: pool, client })
     }
     pub async fn set_json<'a, T>(&self, key: &str, value: T) -> Result<()>
     where
@@ -35,7 +36,20 @@ impl Cache {
         let mut conn = self.redis.get().await?;
         let bytes = serde_json::to_string(&value).map(|s| s.as_bytes().to_vec())?;
 
-        conn.set(key.to_owned(), bytes).await?;
+        conn.set::<_, _, ()>(key.to_owned(), bytes).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_json_with_ttl<T>(&self, key: &str, value: T, ttl: Duration) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let mut conn = self.redis.get().await?;
+        let bytes = serde_json::to_string(&value).map(|s| s.as_bytes().to_vec())?;
+
+        conn.set_ex::<_, _, ()>(key.to_owned(), bytes, ttl.as_secs().max(1) as usize)
+            .await?;
 
         Ok(())
     }
@@ -60,9 +74,48 @@ impl Cache {
 
     pub async fn evict(&self, key: &str) -> Result<()> {
         let mut conn = self.redis.get().await?;
-        conn.del(key).await?;
+        conn.del::<_, ()>(key).await?;
         Ok(())
     }
 
+    // Deletes every key matching a redis glob pattern, e.g. `airtable:abc:*`.
+    pub async fn evict_matching(&self, pattern: &str) -> Result<()> {
+        let mut conn = self.redis.get().await?;
+
+        let keys = {
+            let mut iter = conn.scan_match::<_, String>(pattern).await?;
+            let mut keys = vec![];
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+            keys
+        };
+
+        if !keys.is_empty() {
+            conn.del::<_, ()>(keys).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn publish_json<T>(&self, channel: &str, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let mut conn = self.redis.get().await?;
+        let json = serde_json::to_string(value)?;
+
+        conn.publish::<_, _, ()>(channel, json).await?;
+
+        Ok(())
+    }
+
+    // pub/sub connections can't be returned to the pool, so subscribers get a dedicated connection
+    pub async fn subscribe(&self, channel: &str) -> Result<PubSub> {
+        let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(channel).await?;
+        Ok(pubsub)
+    }
+
     pub async fn save_datasource_view() {}
 }
@@ -1,10 +1,11 @@
 use anyhow::Error;
+use chrono::{DateTime, Utc};
 use derive_builder::Builder;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
 
-use super::types::{JobStatus, JobType, SupportedDatasource};
+use super::types::{JobItemStatus, JobStatus, JobType, RecordChangeType, RefreshSchedule, SupportedDatasource};
 
 #[derive(Debug, Builder, Clone, Serialize, Deserialize)]
 #[builder(setter(into))]
@@ -32,6 +33,10 @@ pub struct CreateDatasourceView {
     pub datasource: SupportedDatasource,
     pub description: String,
     pub metadata: Value,
+    #[builder(default)]
+    pub refresh_schedule: Option<RefreshSchedule>,
+    #[builder(default)]
+    pub next_refresh_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Builder, Clone, Serialize, Deserialize)]
@@ -84,6 +89,16 @@ pub struct EditJob {
     pub metadata: Value,
 }
 
+#[derive(Debug, Builder, Clone, Serialize, Deserialize)]
+#[builder(setter(into))]
+pub struct CreateJobItem {
+    pub job_id: Uuid,
+    pub item: String,
+    pub status: JobItemStatus,
+    #[builder(default)]
+    pub reason: Option<String>,
+}
+
 #[derive(Debug, Builder, Clone, Serialize, Deserialize)]
 #[builder(setter(into))]
 pub struct CreateDatasourceViewJob {
@@ -100,4 +115,66 @@ pub struct CreateExportedUser {
     pub personal_email: String,
     pub generated_email: String,
     pub exported_from: SupportedDatasource,
+    #[builder(default)]
+    pub source_record_id: Option<String>,
+}
+
+#[derive(Debug, Builder, Clone, Serialize, Deserialize)]
+#[builder(setter(into))]
+pub struct ClaimIdempotencyKey {
+    pub owner: String,
+    pub idempotency_key: String,
+    pub request_path: String,
+    pub request_hash: String,
+}
+
+#[derive(Debug, Builder, Clone, Serialize, Deserialize)]
+#[builder(setter(into))]
+pub struct CreateAirtableWebhook {
+    pub datasource_view_id: Uuid,
+    pub base_id: String,
+    pub webhook_id: String,
+    pub mac_secret: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+// A record added, removed or modified between two snapshots of a view. `field_changes` maps each changed field to its
+// `before` and `after` values, with null standing in for a missing field.
+#[derive(Debug, Builder, Clone, Serialize, Deserialize, PartialEq)]
+#[builder(setter(into))]
+pub struct CreateRecordChange {
+    pub source_record_id: String,
+    pub change_type: RecordChangeType,
+    pub field_changes: Value,
+}
+
+// A page of a view's records, narrowed by field filters and a free-text search and ordered by any number of fields
+// before the source order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordQuery {
+    pub filters: Vec<RecordFilter>,
+    pub sort: Vec<RecordSort>,
+    pub search: Option<String>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordFilter {
+    pub field: String,
+    pub condition: FilterCondition,
+}
+
+// `Equals` also matches lists containing the value, and `Contains` is a case-insensitive substring match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FilterCondition {
+    Equals(String),
+    Contains(String),
+    IsEmpty(bool),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordSort {
+    pub field: String,
+    pub descending: bool,
 }
@@ -1,10 +1,12 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::prelude::FromRow;
+use sqlx::{prelude::FromRow, types::Json};
 use uuid::Uuid;
 
-use super::types::{JobStatus, JobType, SupportedDatasource};
+use crate::services::airtable::record::Record;
+
+use super::types::{JobItemStatus, JobStatus, JobType, RecordChangeType, RefreshSchedule, SupportedDatasource};
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 #[serde(rename_all = "camelCase")]
@@ -31,6 +33,9 @@ pub struct DatasourceView {
     pub datasource: SupportedDatasource,
     pub description: String,
     pub metadata: Value,
+    pub refresh_schedule: Option<Json<RefreshSchedule>>,
+    pub next_refresh_at: Option<DateTime<Utc>>,
+    pub last_imported_at: Option<DateTime<Utc>>,
 }
 
 pub type DatasourceViews = Vec<DatasourceView>;
@@ -45,10 +50,29 @@ pub struct Job {
     pub status: JobStatus,
     pub job_type: JobType,
     pub metadata: Value,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub total_items: Option<i32>,
+    pub processed_items: i32,
+    pub last_error: Option<String>,
 }
 
 pub type Jobs = Vec<Job>;
 
+#[derive(Clone, Debug, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct JobItem {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub job_id: Uuid,
+    pub item: String,
+    pub status: JobItemStatus,
+    pub reason: Option<String>,
+}
+
+pub type JobItems = Vec<JobItem>;
+
 #[derive(Clone, Debug, Serialize, Deserialize, FromRow)]
 #[serde(rename_all = "camelCase")]
 pub struct DatasourceViewJob {
@@ -72,6 +96,64 @@ pub struct ExportedUser {
     pub personal_email: String,
     pub generated_email: String,
     pub exported_from: SupportedDatasource,
+    pub source_record_id: Option<String>,
 }
 
 pub type ExportedUsers = Vec<ExportedUser>;
+
+#[derive(Clone, Debug, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct IdempotencyKey {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub owner: String,
+    pub idempotency_key: String,
+    pub request_path: String,
+    pub request_hash: String,
+    pub status_code: Option<i32>,
+    pub content_type: Option<String>,
+    pub response_body: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct AirtableWebhook {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub datasource_view_id: Uuid,
+    pub base_id: String,
+    pub webhook_id: String,
+    #[serde(skip_serializing)]
+    pub mac_secret: String,
+    pub cursor: i64,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub syncing_until: Option<DateTime<Utc>>,
+}
+
+pub type AirtableWebhooks = Vec<AirtableWebhook>;
+
+#[derive(Clone, Debug, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordChange {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub datasource_view_id: Uuid,
+    pub job_id: Option<Uuid>,
+    pub source_record_id: String,
+    pub change_type: RecordChangeType,
+    pub field_changes: Value,
+}
+
+pub type RecordChanges = Vec<RecordChange>;
+
+// One page of a view's records, with the number of records in the view and the number matching the query.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordPage {
+    pub records: Vec<Record<Value>>,
+    pub total: i64,
+    pub matched: i64,
+}
@@ -1,4 +1,9 @@
+use std::fmt;
+
 use anyhow::Error;
+use uuid::Uuid;
+
+use super::types::JobType;
 
 pub struct DbError(Error);
 
@@ -10,3 +15,18 @@ where
         Self(err.into())
     }
 }
+
+// Returned when a job is requested for a datasource view that already has a pending job of the same type.
+#[derive(Debug)]
+pub struct JobConflict {
+    pub job_id: Uuid,
+    pub job_type: JobType,
+}
+
+impl fmt::Display for JobConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a {:?} job is already running for this view", self.job_type)
+    }
+}
+
+impl std::error::Error for JobConflict {}
@@ -1,7 +1,7 @@
 pub mod cache;
 pub mod dto;
 pub mod entities;
-mod errors;
+pub mod errors;
 pub mod sql;
 #[cfg(test)]
 mod tests;
@@ -10,7 +10,14 @@ pub mod types;
 pub use cache::Cache;
 pub use sql::Sql;
 
+use std::collections::{BTreeSet, HashMap, HashSet};
+
 use anyhow::Result;
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use self::{dto::CreateRecordChange, types::RecordChangeType};
+use super::airtable::record::Record;
 
 pub struct Storage {
     pub db: Sql,
@@ -21,4 +28,124 @@ impl Storage {
     pub async fn new(database_url: &str, cache_url: &str) -> Result<Self> {
         todo!()
     }
+
+    // Returns the records imported for a datasource view, or `None` if the view has not been imported yet. Redis is
+    // only a read-through cache in front of postgres, so it falls back to the database when redis is unavailable.
+    pub async fn fetch_view_records(&self, view_id: Uuid) -> Result<Option<Vec<Record<Value>>>> {
+        let key = view_id.to_string();
+
+        match self.cache.get_json::<Vec<Record<Value>>>(&key).await {
+            Ok(Some(records)) => return Ok(Some(records)),
+            Ok(None) => {}
+            Err(e) => log::warn!("unable to read cached records of {view_id}: {e:#}"),
+        }
+
+        let records = self.db.fetch_datasource_records(view_id).await?;
+
+        if let Some(ref records) = records {
+            if let Err(e) = self.cache.set_json(&key, records.clone()).await {
+                log::warn!("unable to cache records of {view_id}: {e:#}");
+            }
+        }
+
+        Ok(records)
+    }
+
+    // Saves a view's full set of records, leaving the next read to repopulate the cache, and returns how they
+    // changed since the last save.
+    pub async fn save_view_records(
+        &self,
+        view_id: Uuid,
+        import_job_id: Option<Uuid>,
+        records: &[Record<Value>],
+    ) -> Result<Vec<CreateRecordChange>> {
+        let changes = self.db.save_datasource_records(view_id, import_job_id, records).await?;
+
+        self.evict_view_records(view_id).await;
+
+        Ok(changes)
+    }
+
+    // Saves changes to some of a view's records, for updates that don't come with the full set, and returns how
+    // they changed.
+    pub async fn save_view_record_changes(
+        &self,
+        view_id: Uuid,
+        records: &[Record<Value>],
+        removed_record_ids: &[String],
+    ) -> Result<Vec<CreateRecordChange>> {
+        let changes = self
+            .db
+            .save_datasource_record_changes(view_id, records, removed_record_ids)
+            .await?;
+
+        self.evict_view_records(view_id).await;
+
+        Ok(changes)
+    }
+
+    // Drops a view's cached records so the next read goes back to postgres. Like the read path this treats redis as
+    // optional, so failures are only logged.
+    pub async fn evict_view_records(&self, view_id: Uuid) {
+        if let Err(e) = self.cache.evict(&view_id.to_string()).await {
+            log::warn!("unable to evict cached records of {view_id}: {e:#}");
+        }
+    }
+}
+
+// Compares two snapshots of a view's records by record id, returning the records that were added, removed or had
+// any of their fields changed.
+pub fn diff_records(previous: &[Record<Value>], current: &[Record<Value>]) -> Vec<CreateRecordChange> {
+    let before = previous
+        .iter()
+        .map(|r| (r.id.as_str(), &r.fields))
+        .collect::<HashMap<&str, &Value>>();
+    let current_ids = current.iter().map(|r| r.id.as_str()).collect::<HashSet<&str>>();
+
+    let mut changes = vec![];
+
+    for record in current {
+        let (change_type, fields_before) = match before.get(record.id.as_str()) {
+            None => (RecordChangeType::Added, None),
+            Some(fields) if **fields != record.fields => (RecordChangeType::Modified, Some(*fields)),
+            Some(_) => continue,
+        };
+
+        changes.push(CreateRecordChange {
+            source_record_id: record.id.clone(),
+            change_type,
+            field_changes: field_changes(fields_before, Some(&record.fields)),
+        });
+    }
+
+    for record in previous.iter().filter(|r| !current_ids.contains(r.id.as_str())) {
+        changes.push(CreateRecordChange {
+            source_record_id: record.id.clone(),
+            change_type: RecordChangeType::Removed,
+            field_changes: field_changes(Some(&record.fields), None),
+        });
+    }
+
+    changes
+}
+
+fn field_changes(before: Option<&Value>, after: Option<&Value>) -> Value {
+    let empty = Map::new();
+    let before = before.and_then(Value::as_object).unwrap_or(&empty);
+    let after = after.and_then(Value::as_object).unwrap_or(&empty);
+
+    before
+        .keys()
+        .chain(after.keys())
+        .collect::<BTreeSet<&String>>()
+        .into_iter()
+        .filter_map(|field| {
+            let (old, new) = (
+                before.get(field).unwrap_or(&Value::Null),
+                after.get(field).unwrap_or(&Value::Null),
+            );
+            (old != new).then(|| (field.clone(), json!({"before": old, "after": new})))
+        })
+        .collect::<Map<String, Value>>()
+        .into()
 }
@@ -1,17 +1,27 @@
-use crate::services::workspace::users::Email;
+use std::collections::HashMap;
+
+use crate::services::{airtable::record::Record, workspace::users::Email};
 
 use super::{
+    diff_records,
     dto::{
-        CreateDatasourceView, CreateDatasourceViewJob, CreateExportedUser, CreateJob, CreateJobWithDatasource,
-        CreateUser, EditDatasourceView, EditJob, EditUser,
+        ClaimIdempotencyKey, CreateAirtableWebhook, CreateDatasourceView, CreateDatasourceViewJob, CreateExportedUser,
+        CreateJob, CreateJobItem, CreateJobWithDatasource, CreateRecordChange, CreateUser, EditDatasourceView, EditJob,
+        EditUser, FilterCondition, RecordQuery,
     },
     entities::{
-        DatasourceView, DatasourceViewJob, DatasourceViewJobs, DatasourceViews, ExportedUser, ExportedUsers, Job, Jobs,
-        User,
+        AirtableWebhook, AirtableWebhooks, DatasourceView, DatasourceViewJob, DatasourceViewJobs, DatasourceViews,
+        ExportedUser, ExportedUsers, IdempotencyKey, Job, JobItem, JobItems, Jobs, RecordChange, RecordChanges,
+        RecordPage, User,
     },
+    errors::JobConflict,
+    types::{JobType, RefreshSchedule},
 };
 use anyhow::Result;
-use sqlx::{postgres::PgPoolOptions, PgPool, Postgres, QueryBuilder};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::{postgres::PgPoolOptions, types::Json, PgPool, Postgres, QueryBuilder, Transaction};
 use uuid::Uuid;
 
 pub struct Sql {
@@ -19,6 +29,10 @@ pub struct Sql {
 }
 
 impl Sql {
+    // postgres allows at most 65535 bind parameters per statement
+    const RECORDS_PER_UPSERT: usize = 5000;
+    const STALE_JOB_ERROR: &'static str = "job stopped reporting progress and was abandoned";
+
     pub async fn new(pg_uri: &str) -> Result<Self> {
         let sql = PgPoolOptions::new().max_connections(100).connect(pg_uri).await?;
 
@@ -84,8 +98,9 @@ impl Sql {
     pub async fn create_datasource_view(&self, data: CreateDatasourceView) -> Result<String> {
         let mut txn = self.pool.begin().await?;
         let (datasource_view_id,) = sqlx::query_as::<_, (Uuid,)>(
-            "insert into datasource_views (user_id, view_name, datasource, description, metadata)
-            values ($1, $2, $3, $4, $5)
+            "insert into datasource_views
+            (user_id, view_name, datasource, description, metadata, refresh_schedule, next_refresh_at)
+            values ($1, $2, $3, $4, $5, $6, $7)
             returning id",
         )
         .bind(data.user_id)
@@ -93,6 +108,8 @@ impl Sql {
         .bind(data.datasource)
         .bind(&data.description)
         .bind(&data.metadata)
+        .bind(data.refresh_schedule.map(Json))
+        .bind(data.next_refresh_at)
         .fetch_one(&mut *txn)
         .await?;
 
@@ -136,7 +153,7 @@ impl Sql {
     pub async fn fetch_datasource_view(&self, datasource_view_id: Uuid) -> Result<Option<DatasourceView>> {
         let datasource_view = sqlx::query_as::<_, DatasourceView>(
             "select id, created_at, updated_at, 
-             user_id, view_name, datasource, description, metadata 
+             user_id, view_name, datasource, description, metadata, refresh_schedule, next_refresh_at, last_imported_at
              from datasource_views where id = $1",
         )
         .bind(datasource_view_id)
@@ -149,7 +166,8 @@ impl Sql {
     pub async fn fetch_datasource_views(&self) -> Result<DatasourceViews> {
         let datasource_view = sqlx::query_as::<_, DatasourceView>(
             "select id, created_at, updated_at, 
-             user_id, view_name, datasource, description, metadata from datasource_views",
+             user_id, view_name, datasource, description, metadata, refresh_schedule, next_refresh_at, last_imported_at
+             from datasource_views",
         )
         .fetch_all(&self.pool)
         .await?;
@@ -157,6 +175,75 @@ impl Sql {
         Ok(datasource_view)
     }
 
+    pub async fn set_datasource_view_schedule(
+        &self,
+        datasource_view_id: Uuid,
+        schedule: Option<RefreshSchedule>,
+        next_refresh_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let mut txn = self.pool.begin().await?;
+        sqlx::query("update datasource_views set refresh_schedule = $1, next_refresh_at = $2 where id = $3")
+            .bind(schedule.map(Json))
+            .bind(next_refresh_at)
+            .bind(datasource_view_id)
+            .execute(&mut *txn)
+            .await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn set_datasource_view_metadata(&self, datasource_view_id: Uuid, metadata: &Value) -> Result<()> {
+        let mut txn = self.pool.begin().await?;
+        sqlx::query("update datasource_views set metadata = $1 where id = $2")
+            .bind(metadata)
+            .bind(datasource_view_id)
+            .execute(&mut *txn)
+            .await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_due_datasource_views(&self) -> Result<DatasourceViews> {
+        let datasource_views = sqlx::query_as::<_, DatasourceView>(
+            "select id, created_at, updated_at,
+             user_id, view_name, datasource, description, metadata, refresh_schedule, next_refresh_at, last_imported_at
+             from datasource_views
+             where refresh_schedule is not null and next_refresh_at <= current_timestamp",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(datasource_views)
+    }
+
+    // Moves a view's next run forward only if nobody else has done so since it was read, so a refresh is scheduled
+    // at most once when several instances run the scheduler.
+    pub async fn claim_scheduled_refresh(
+        &self,
+        datasource_view_id: Uuid,
+        expected_next_refresh_at: DateTime<Utc>,
+        next_refresh_at: DateTime<Utc>,
+    ) -> Result<bool> {
+        let mut txn = self.pool.begin().await?;
+        let res = sqlx::query(
+            "update datasource_views set next_refresh_at = $1
+             where id = $2 and next_refresh_at = $3",
+        )
+        .bind(next_refresh_at)
+        .bind(datasource_view_id)
+        .bind(expected_next_refresh_at)
+        .execute(&mut *txn)
+        .await?;
+
+        txn.commit().await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
     // Job methods
     pub async fn create_job(&self, data: CreateJob) -> Result<String> {
         let mut txn = self.pool.begin().await?;
@@ -177,20 +264,77 @@ impl Sql {
         Ok(job_id.to_string())
     }
 
-    pub async fn create_job_with_datasource(&self, data: CreateJobWithDatasource) -> Result<(String, String)> {
+    // Serializes job creation per (view, job type) with a transaction scoped advisory lock, so at most one job of
+    // each type can be pending for a view even when requests race across instances. A pending job that has not
+    // reported progress within `stale_seconds` is assumed to have died with its worker and no longer blocks.
+    pub async fn create_job_with_datasource(
+        &self,
+        data: CreateJobWithDatasource,
+        stale_seconds: i64,
+    ) -> Result<(String, String)> {
         let mut txn = self.pool.begin().await?;
-        let job_id = self.create_job(data.clone().try_into()?).await?;
+
+        sqlx::query("select pg_advisory_xact_lock(hashtextextended(concat($1::uuid, ':', $2::job_type), 0))")
+            .bind(data.datasource_view_id)
+            .bind(data.job_type)
+            .execute(&mut *txn)
+            .await?;
+
+        sqlx::query(
+            "update jobs j set status='error'::job_status, finished_at=current_timestamp, last_error=$4
+             from datasource_view_jobs dvj
+             where j.id = dvj.job_id and dvj.datasource_view_id = $1 and j.job_type = $2
+             and j.status = 'pending'::job_status and j.updated_at < current_timestamp - make_interval(secs => $3)",
+        )
+        .bind(data.datasource_view_id)
+        .bind(data.job_type)
+        .bind(stale_seconds as f64)
+        .bind(Self::STALE_JOB_ERROR)
+        .execute(&mut *txn)
+        .await?;
+
+        let pending = sqlx::query_as::<_, (Uuid,)>(
+            "select j.id from jobs j
+             join datasource_view_jobs dvj on j.id = dvj.job_id
+             where dvj.datasource_view_id = $1 and j.job_type = $2 and j.status = 'pending'::job_status
+             limit 1",
+        )
+        .bind(data.datasource_view_id)
+        .bind(data.job_type)
+        .fetch_optional(&mut *txn)
+        .await?;
+
+        if let Some((job_id,)) = pending {
+            return Err(JobConflict {
+                job_id,
+                job_type: data.job_type,
+            }
+            .into());
+        }
+
+        let (job_id,) = sqlx::query_as::<_, (Uuid,)>(
+            "insert into jobs (user_id, status, job_type, metadata)
+            values ($1, $2, $3, $4)
+            returning id",
+        )
+        .bind(data.user_id)
+        .bind(data.status)
+        .bind(data.job_type)
+        .bind(&data.metadata)
+        .fetch_one(&mut *txn)
+        .await?;
+
         let (datasource_view_job_id,) = sqlx::query_as::<_, (Uuid,)>(
             "insert into datasource_view_jobs (job_id, datasource_view_id) 
              values ($1, $2) returning id",
         )
-        .bind(Uuid::parse_str(&job_id)?)
+        .bind(job_id)
         .bind(data.datasource_view_id)
         .fetch_one(&mut *txn)
         .await?;
 
         txn.commit().await?;
-        Ok((job_id, datasource_view_job_id.into()))
+        Ok((job_id.to_string(), datasource_view_job_id.into()))
     }
 
     pub async fn edit_job(&self, job_id: &str, data: EditJob) -> Result<()> {
@@ -235,7 +379,8 @@ impl Sql {
 
     pub async fn fetch_jobs(&self) -> Result<Jobs> {
         let jobs = sqlx::query_as::<_, Job>(
-            "select id, created_at, updated_at, user_id, status, job_type, metadata from jobs",
+            "select id, created_at, updated_at, user_id, status, job_type, metadata,
+             started_at, finished_at, total_items, processed_items, last_error from jobs",
         )
         .fetch_all(&self.pool)
         .await?;
@@ -243,26 +388,119 @@ impl Sql {
         Ok(jobs)
     }
 
-    pub async fn mark_job_errored(&self, job_id: Uuid) -> Result<()> {
+    pub async fn mark_job_started(&self, job_id: Uuid, total_items: Option<i32>) -> Result<()> {
         let mut txn = self.pool.begin().await?;
-        sqlx::query("update jobs set status='error'::job_status where id=$1")
+        sqlx::query("update jobs set started_at=current_timestamp, total_items=$2 where id=$1")
             .bind(job_id)
+            .bind(total_items)
             .execute(&mut *txn)
             .await?;
         txn.commit().await?;
         Ok(())
     }
 
-    pub async fn mark_job_complete(&self, job_id: Uuid) -> Result<()> {
+    pub async fn update_job_progress(
+        &self,
+        job_id: Uuid,
+        processed_items: i32,
+        total_items: Option<i32>,
+    ) -> Result<()> {
         let mut txn = self.pool.begin().await?;
-        sqlx::query("update jobs set status='complete'::job_status where id=$1")
+        sqlx::query("update jobs set processed_items=$2, total_items=coalesce($3, total_items) where id=$1")
             .bind(job_id)
+            .bind(processed_items)
+            .bind(total_items)
             .execute(&mut *txn)
             .await?;
         txn.commit().await?;
         Ok(())
     }
 
+    // Only a pending job can fail, returns whether this one did.
+    pub async fn mark_job_errored(&self, job_id: Uuid, error: &str) -> Result<bool> {
+        let mut txn = self.pool.begin().await?;
+        let result = sqlx::query(
+            "update jobs set status='error'::job_status, finished_at=current_timestamp, last_error=$2
+             where id=$1 and status = 'pending'::job_status",
+        )
+        .bind(job_id)
+        .bind(error)
+        .execute(&mut *txn)
+        .await?;
+        txn.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Marks a pending job as alive, the update trigger moves its `updated_at`.
+    pub async fn touch_job(&self, job_id: Uuid) -> Result<()> {
+        sqlx::query("update jobs set updated_at = current_timestamp where id=$1 and status = 'pending'::job_status")
+            .bind(job_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Fails pending jobs that stopped reporting progress, e.g. because the instance running them was restarted.
+    pub async fn fail_stale_jobs(&self, stale_seconds: i64) -> Result<u64> {
+        let mut txn = self.pool.begin().await?;
+        let result = sqlx::query(
+            "update jobs set status='error'::job_status, finished_at=current_timestamp, last_error=$2
+             where status = 'pending'::job_status and updated_at < current_timestamp - make_interval(secs => $1)",
+        )
+        .bind(stale_seconds as f64)
+        .bind(Self::STALE_JOB_ERROR)
+        .execute(&mut *txn)
+        .await?;
+        txn.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    // Only a pending job can complete, returns whether this one did.
+    pub async fn mark_job_complete(&self, job_id: Uuid) -> Result<bool> {
+        let mut txn = self.pool.begin().await?;
+        let result = sqlx::query(
+            "update jobs set status='complete'::job_status, finished_at=current_timestamp
+             where id=$1 and status = 'pending'::job_status",
+        )
+        .bind(job_id)
+        .execute(&mut *txn)
+        .await?;
+        txn.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // JobItem methods
+    pub async fn record_job_item(&self, data: CreateJobItem) -> Result<(i32, Option<i32>)> {
+        let mut txn = self.pool.begin().await?;
+        sqlx::query("insert into job_items (job_id, item, status, reason) values ($1, $2, $3, $4)")
+            .bind(data.job_id)
+            .bind(&data.item)
+            .bind(data.status)
+            .bind(&data.reason)
+            .execute(&mut *txn)
+            .await?;
+        let progress = sqlx::query_as::<_, (i32, Option<i32>)>(
+            "update jobs set processed_items=processed_items + 1 where id=$1
+             returning processed_items, total_items",
+        )
+        .bind(data.job_id)
+        .fetch_one(&mut *txn)
+        .await?;
+        txn.commit().await?;
+        Ok(progress)
+    }
+
+    pub async fn fetch_job_items(&self, job_id: Uuid) -> Result<JobItems> {
+        let items = sqlx::query_as::<_, JobItem>(
+            "select id, created_at, updated_at, job_id, item, status, reason
+             from job_items where job_id=$1 order by created_at",
+        )
+        .bind(job_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(items)
+    }
+
     // DatasourceViewJob methods
     pub async fn create_datasource_view_job(&self, data: CreateDatasourceViewJob) -> Result<String> {
         let mut txn = self.pool.begin().await?;
@@ -331,7 +569,7 @@ impl Sql {
 
     pub async fn delete_exported_user_by_email(&self, email: &str) -> Result<()> {
         let mut txn = self.pool.begin().await?;
-        sqlx::query("delete from exported_users where email = $1")
+        sqlx::query("delete from exported_users where generated_email = $1")
             .bind(email)
             .execute(&mut *txn)
             .await?;
@@ -343,7 +581,8 @@ impl Sql {
 
     pub async fn fetch_exported_user(&self, exported_user_id: &str) -> Result<Option<ExportedUser>> {
         let exported_user = sqlx::query_as::<_, ExportedUser>(
-            "select id, created_at, updated_at, job_id, first_name, last_name, personal_email, generated_email, exported_from 
+            "select id, created_at, updated_at, job_id, first_name, last_name, personal_email, generated_email, exported_from,
+            source_record_id
             from exported_users where id = $1",
         )
         .bind(Uuid::parse_str(exported_user_id)?)
@@ -354,7 +593,8 @@ impl Sql {
 
     pub async fn fetch_exported_users(&self) -> Result<ExportedUsers> {
         let exported_users = sqlx::query_as::<_, ExportedUser>(
-            "select id, created_at, updated_at, job_id, first_name, last_name, personal_email, generated_email, exported_from 
+            "select id, created_at, updated_at, job_id, first_name, last_name, personal_email, generated_email, exported_from,
+            source_record_id
             from exported_users",
         )
         .fetch_all(&self.pool)
@@ -364,10 +604,15 @@ impl Sql {
     }
 
     pub async fn save_exported_users(&self, users: Vec<CreateExportedUser>) -> Result<()> {
+        if users.is_empty() {
+            return Ok(());
+        }
+
         let mut txn = self.pool.begin().await?;
 
         QueryBuilder::<Postgres>::new(
-            "insert into exported_users (job_id, first_name, last_name, personal_email, generated_email, exported_from) ",
+            "insert into exported_users (job_id, first_name, last_name, personal_email, generated_email, exported_from,
+             source_record_id) ",
         )
         .push_values(users.into_iter(), |mut b, p| {
             b.push_bind(p.job_id)
@@ -375,7 +620,8 @@ impl Sql {
                 .push_bind(p.last_name)
                 .push_bind(p.personal_email)
                 .push_bind(p.generated_email)
-                .push_bind(p.exported_from);
+                .push_bind(p.exported_from)
+                .push_bind(p.source_record_id);
         })
         .build()
         .execute(&mut *txn)
@@ -387,7 +633,8 @@ impl Sql {
 
     pub async fn fetch_datasource_view_jobs(&self, datasource_view_id: Uuid) -> Result<Jobs> {
         let jobs = sqlx::query_as::<_, Job>(
-            "select j.id, j.created_at, j.updated_at, j.user_id, j.status, j.job_type, j.metadata
+            "select j.id, j.created_at, j.updated_at, j.user_id, j.status, j.job_type, j.metadata,
+             j.started_at, j.finished_at, j.total_items, j.processed_items, j.last_error
              from jobs j
              join datasource_view_jobs dvj ON j.id = dvj.job_id
              where dvj.datasource_view_id=$1",
@@ -398,9 +645,41 @@ impl Sql {
         Ok(jobs)
     }
 
+    pub async fn fetch_job_datasource_view_id(&self, job_id: Uuid) -> Result<Option<Uuid>> {
+        let view_id =
+            sqlx::query_as::<_, (Uuid,)>("select datasource_view_id from datasource_view_jobs where job_id=$1")
+                .bind(job_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(view_id.map(|(id,)| id))
+    }
+
+    pub async fn fetch_pending_view_job(
+        &self,
+        datasource_view_id: Uuid,
+        job_type: JobType,
+        stale_seconds: i64,
+    ) -> Result<Option<Uuid>> {
+        let job_id = sqlx::query_as::<_, (Uuid,)>(
+            "select j.id from jobs j
+             join datasource_view_jobs dvj on j.id = dvj.job_id
+             where dvj.datasource_view_id = $1 and j.job_type = $2 and j.status = 'pending'::job_status
+             and j.updated_at >= current_timestamp - make_interval(secs => $3)
+             order by j.created_at desc
+             limit 1",
+        )
+        .bind(datasource_view_id)
+        .bind(job_type)
+        .bind(stale_seconds as f64)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(job_id.map(|(id,)| id))
+    }
+
     pub async fn fetch_exported_users_by_job(&self, job_id: Uuid) -> Result<ExportedUsers> {
         let users = sqlx::query_as::<_, ExportedUser>(
-            "select id, created_at, updated_at, job_id, first_name, last_name, personal_email, generated_email, exported_from 
+            "select id, created_at, updated_at, job_id, first_name, last_name, personal_email, generated_email, exported_from,
+            source_record_id
             from exported_users where job_id=$1",
         )
         .bind(job_id)
@@ -411,9 +690,578 @@ impl Sql {
 
     pub async fn fetch_exported_users_by_view(&self, view_id: Uuid) -> Result<ExportedUsers> {
         let users = sqlx::query_as::<_, ExportedUser>(
-            "select id, created_at, updated_at, job_id, first_name, last_name, personal_email, generated_email, exported_from 
+            "select id, created_at, updated_at, job_id, first_name, last_name, personal_email, generated_email, exported_from,
+            source_record_id
             from exported_users where job_id in (select job_id from datasource_view_jobs where datasource_view_id=$1)",
         ).bind(view_id).fetch_all(&self.pool).await?;
         Ok(users)
     }
+
+    // IdempotencyKey methods
+
+    // Reserves the key for a new request, taking over keys older than the replay window. Returns `None` when the key
+    // is already held by a request within the window.
+    pub async fn claim_idempotency_key(&self, data: ClaimIdempotencyKey, window_seconds: i64) -> Result<Option<Uuid>> {
+        let claimed = sqlx::query_as::<_, (Uuid,)>(
+            "insert into idempotency_keys (owner, idempotency_key, request_path, request_hash)
+             values ($1, $2, $3, $4)
+             on conflict (owner, idempotency_key) do update
+             set created_at = current_timestamp, request_path = excluded.request_path,
+                 request_hash = excluded.request_hash, status_code = null, content_type = null, response_body = null
+             where idempotency_keys.created_at < current_timestamp - make_interval(secs => $5)
+             returning id",
+        )
+        .bind(&data.owner)
+        .bind(&data.idempotency_key)
+        .bind(&data.request_path)
+        .bind(&data.request_hash)
+        .bind(window_seconds as f64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(claimed.map(|(id,)| id))
+    }
+
+    pub async fn fetch_idempotency_key(&self, owner: &str, idempotency_key: &str) -> Result<Option<IdempotencyKey>> {
+        let key = sqlx::query_as::<_, IdempotencyKey>(
+            "select id, created_at, updated_at, owner, idempotency_key, request_path, request_hash,
+             status_code, content_type, response_body
+             from idempotency_keys where owner = $1 and idempotency_key = $2",
+        )
+        .bind(owner)
+        .bind(idempotency_key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    pub async fn save_idempotent_response(
+        &self,
+        id: Uuid,
+        status_code: i32,
+        content_type: Option<String>,
+        response_body: Vec<u8>,
+    ) -> Result<()> {
+        let mut txn = self.pool.begin().await?;
+        sqlx::query(
+            "update idempotency_keys set status_code = $1, content_type = $2, response_body = $3
+             where id = $4",
+        )
+        .bind(status_code)
+        .bind(content_type)
+        .bind(response_body)
+        .bind(id)
+        .execute(&mut *txn)
+        .await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn release_idempotency_key(&self, id: Uuid) -> Result<()> {
+        let mut txn = self.pool.begin().await?;
+        sqlx::query("delete from idempotency_keys where id = $1")
+            .bind(id)
+            .execute(&mut *txn)
+            .await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn create_airtable_webhook(&self, data: CreateAirtableWebhook) -> Result<()> {
+        let mut txn = self.pool.begin().await?;
+        sqlx::query(
+            "insert into airtable_webhooks (datasource_view_id, base_id, webhook_id, mac_secret, expires_at)
+             values ($1, $2, $3, $4, $5)",
+        )
+        .bind(data.datasource_view_id)
+        .bind(&data.base_id)
+        .bind(&data.webhook_id)
+        .bind(&data.mac_secret)
+        .bind(data.expires_at)
+        .execute(&mut *txn)
+        .await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_airtable_webhook(&self, webhook_id: &str) -> Result<Option<AirtableWebhook>> {
+        let webhook = sqlx::query_as::<_, AirtableWebhook>(
+            "select id, created_at, updated_at, datasource_view_id, base_id, webhook_id, mac_secret,
+             cursor, expires_at, syncing_until
+             from airtable_webhooks where webhook_id = $1",
+        )
+        .bind(webhook_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn fetch_view_airtable_webhook(&self, datasource_view_id: Uuid) -> Result<Option<AirtableWebhook>> {
+        let webhook = sqlx::query_as::<_, AirtableWebhook>(
+            "select id, created_at, updated_at, datasource_view_id, base_id, webhook_id, mac_secret,
+             cursor, expires_at, syncing_until
+             from airtable_webhooks where datasource_view_id = $1",
+        )
+        .bind(datasource_view_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn delete_airtable_webhook(&self, webhook_id: &str) -> Result<()> {
+        sqlx::query("delete from airtable_webhooks where webhook_id = $1")
+            .bind(webhook_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_expiring_airtable_webhooks(&self, before: DateTime<Utc>) -> Result<AirtableWebhooks> {
+        let webhooks = sqlx::query_as::<_, AirtableWebhook>(
+            "select id, created_at, updated_at, datasource_view_id, base_id, webhook_id, mac_secret,
+             cursor, expires_at, syncing_until
+             from airtable_webhooks where expires_at <= $1",
+        )
+        .bind(before)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    pub async fn set_airtable_webhook_expiry(&self, webhook_id: &str, expires_at: Option<DateTime<Utc>>) -> Result<()> {
+        let mut txn = self.pool.begin().await?;
+        sqlx::query("update airtable_webhooks set expires_at = $1 where webhook_id = $2")
+            .bind(expires_at)
+            .bind(webhook_id)
+            .execute(&mut *txn)
+            .await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    // Takes a lease on syncing the webhook's payloads so concurrent notifications don't apply the same payloads
+    // twice. Returns nothing if another sync holds an unexpired lease.
+    pub async fn claim_airtable_webhook_sync(
+        &self,
+        webhook_id: &str,
+        lease_seconds: i64,
+    ) -> Result<Option<AirtableWebhook>> {
+        let webhook = sqlx::query_as::<_, AirtableWebhook>(
+            "update airtable_webhooks set syncing_until = current_timestamp + make_interval(secs => $2)
+             where webhook_id = $1 and (syncing_until is null or syncing_until < current_timestamp)
+             returning id, created_at, updated_at, datasource_view_id, base_id, webhook_id, mac_secret,
+             cursor, expires_at, syncing_until",
+        )
+        .bind(webhook_id)
+        .bind(lease_seconds as f64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn release_airtable_webhook_sync(&self, webhook_id: &str, cursor: i64) -> Result<()> {
+        let mut txn = self.pool.begin().await?;
+        sqlx::query("update airtable_webhooks set cursor = $1, syncing_until = null where webhook_id = $2")
+            .bind(cursor)
+            .bind(webhook_id)
+            .execute(&mut *txn)
+            .await?;
+
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    // Upserts the full set of a view's records in their source order, marking the ones that are gone as removed, and
+    // records when the view was last imported. Unchanged records keep their row, so `last_seen_at` is when a record
+    // last changed and the view's `last_imported_at` when it was last seen. The changes from the previous set are
+    // stored and returned.
+    pub async fn save_datasource_records(
+        &self,
+        datasource_view_id: Uuid,
+        import_job_id: Option<Uuid>,
+        records: &[Record<Value>],
+    ) -> Result<Vec<CreateRecordChange>> {
+        let mut txn = self.pool.begin().await?;
+
+        // saves of a view wait on each other so every diff is taken against the records it replaces
+        sqlx::query("select id from datasource_views where id = $1 for update")
+            .bind(datasource_view_id)
+            .execute(&mut *txn)
+            .await?;
+
+        let rows = sqlx::query_as::<_, (String, Value, String, String, i32)>(
+            "select source_record_id, fields, source_created_time, content_hash, position from datasource_records
+             where datasource_view_id = $1 and removed_at is null",
+        )
+        .bind(datasource_view_id)
+        .fetch_all(&mut *txn)
+        .await?;
+
+        let saved = rows
+            .iter()
+            .map(|(id, _, created_time, hash, position)| {
+                (id.as_str(), (hash.as_str(), created_time.as_str(), *position))
+            })
+            .collect::<HashMap<&str, (&str, &str, i32)>>();
+
+        // rows that would be written back as they are, content and position alike, are left alone
+        let hashes = records.iter().map(|r| content_hash(&r.fields)).collect::<Vec<String>>();
+        let updates = records
+            .iter()
+            .zip(&hashes)
+            .enumerate()
+            .filter(|(i, (r, hash))| {
+                saved.get(r.id.as_str()) != Some(&(hash.as_str(), r.created_time.as_str(), *i as i32))
+            })
+            .collect::<Vec<_>>();
+
+        let previous = rows
+            .into_iter()
+            .map(|(id, fields, created_time, _, _)| Record {
+                id,
+                fields,
+                created_time,
+            })
+            .collect::<Vec<Record<Value>>>();
+
+        let changes = diff_records(&previous, records);
+
+        for chunk in updates.chunks(Self::RECORDS_PER_UPSERT) {
+            QueryBuilder::<Postgres>::new(
+                "insert into datasource_records (datasource_view_id, source_record_id, position, fields, content_hash,
+                 source_created_time, import_job_id) ",
+            )
+            .push_values(chunk, |mut b, (i, (r, hash))| {
+                b.push_bind(datasource_view_id)
+                    .push_bind(&r.id)
+                    .push_bind(*i as i32)
+                    .push_bind(&r.fields)
+                    .push_bind(hash.as_str())
+                    .push_bind(&r.created_time)
+                    .push_bind(import_job_id);
+            })
+            .push(
+                " on conflict (datasource_view_id, source_record_id) do update
+                 set position = excluded.position, fields = excluded.fields, content_hash = excluded.content_hash,
+                     source_created_time = excluded.source_created_time,
+                     import_job_id = coalesce(excluded.import_job_id, datasource_records.import_job_id),
+                     last_seen_at = current_timestamp, removed_at = null",
+            )
+            .build()
+            .execute(&mut *txn)
+            .await?;
+        }
+
+        let record_ids = records.iter().map(|r| r.id.clone()).collect::<Vec<String>>();
+        sqlx::query(
+            "update datasource_records set removed_at = current_timestamp
+             where datasource_view_id = $1 and removed_at is null and not (source_record_id = any($2))",
+        )
+        .bind(datasource_view_id)
+        .bind(&record_ids)
+        .execute(&mut *txn)
+        .await?;
+
+        sqlx::query("update datasource_views set last_imported_at = current_timestamp where id = $1")
+            .bind(datasource_view_id)
+            .execute(&mut *txn)
+            .await?;
+
+        insert_record_changes(&mut txn, datasource_view_id, import_job_id, &changes).await?;
+
+        txn.commit().await?;
+
+        Ok(changes)
+    }
+
+    // Upserts some of a view's records and marks others as removed, leaving the rest alone. Unlike a full save this
+    // can't undo records saved by an import that finished in the meantime. New records go after the existing ones.
+    pub async fn save_datasource_record_changes(
+        &self,
+        datasource_view_id: Uuid,
+        records: &[Record<Value>],
+        removed_record_ids: &[String],
+    ) -> Result<Vec<CreateRecordChange>> {
+        let mut txn = self.pool.begin().await?;
+
+        sqlx::query("select id from datasource_views where id = $1 for update")
+            .bind(datasource_view_id)
+            .execute(&mut *txn)
+            .await?;
+
+        let record_ids = records
+            .iter()
+            .map(|r| r.id.clone())
+            .chain(removed_record_ids.iter().cloned())
+            .collect::<Vec<String>>();
+
+        let previous = sqlx::query_as::<_, (String, Value, String)>(
+            "select source_record_id, fields, source_created_time from datasource_records
+             where datasource_view_id = $1 and removed_at is null and source_record_id = any($2)",
+        )
+        .bind(datasource_view_id)
+        .bind(&record_ids)
+        .fetch_all(&mut *txn)
+        .await?
+        .into_iter()
+        .map(|(id, fields, created_time)| Record {
+            id,
+            fields,
+            created_time,
+        })
+        .collect::<Vec<Record<Value>>>();
+
+        let changes = diff_records(&previous, records);
+
+        let (next_position,) = sqlx::query_as::<_, (i32,)>(
+            "select coalesce(max(position) + 1, 0) from datasource_records where datasource_view_id = $1",
+        )
+        .bind(datasource_view_id)
+        .fetch_one(&mut *txn)
+        .await?;
+
+        for (n, chunk) in records.chunks(Self::RECORDS_PER_UPSERT).enumerate() {
+            let offset = next_position as usize + n * Self::RECORDS_PER_UPSERT;
+
+            QueryBuilder::<Postgres>::new(
+                "insert into datasource_records (datasource_view_id, source_record_id, position, fields, content_hash,
+                 source_created_time) ",
+            )
+            .push_values(chunk.iter().enumerate(), |mut b, (i, r)| {
+                b.push_bind(datasource_view_id)
+                    .push_bind(&r.id)
+                    .push_bind((offset + i) as i32)
+                    .push_bind(&r.fields)
+                    .push_bind(content_hash(&r.fields))
+                    .push_bind(&r.created_time);
+            })
+            .push(
+                " on conflict (datasource_view_id, source_record_id) do update
+                 set fields = excluded.fields, content_hash = excluded.content_hash,
+                     last_seen_at = current_timestamp, removed_at = null,
+                     position = case when datasource_records.removed_at is null
+                                     then datasource_records.position else excluded.position end",
+            )
+            .build()
+            .execute(&mut *txn)
+            .await?;
+        }
+
+        sqlx::query(
+            "update datasource_records set removed_at = current_timestamp
+             where datasource_view_id = $1 and removed_at is null and source_record_id = any($2)",
+        )
+        .bind(datasource_view_id)
+        .bind(removed_record_ids)
+        .execute(&mut *txn)
+        .await?;
+
+        insert_record_changes(&mut txn, datasource_view_id, None, &changes).await?;
+
+        txn.commit().await?;
+
+        Ok(changes)
+    }
+
+    // Filters, searches, sorts and pages a view's current records in postgres, so only the page leaves the database.
+    pub async fn query_datasource_records(&self, datasource_view_id: Uuid, query: &RecordQuery) -> Result<RecordPage> {
+        let mut counts = QueryBuilder::<Postgres>::new("select count(*), count(*) filter (where ");
+        push_record_conditions(&mut counts, query);
+        counts
+            .push(") from datasource_records where removed_at is null and datasource_view_id = ")
+            .push_bind(datasource_view_id);
+
+        let (total, matched) = counts.build_query_as::<(i64, i64)>().fetch_one(&self.pool).await?;
+
+        let mut page = QueryBuilder::<Postgres>::new(
+            "select source_record_id, fields, source_created_time from datasource_records
+             where removed_at is null and datasource_view_id = ",
+        );
+        page.push_bind(datasource_view_id).push(" and ");
+        push_record_conditions(&mut page, query);
+
+        page.push(" order by ");
+        for sort in &query.sort {
+            page.push("fields -> ")
+                .push_bind(sort.field.clone())
+                .push(if sort.descending {
+                    " desc nulls last, "
+                } else {
+                    " asc nulls last, "
+                });
+        }
+        page.push("position limit ")
+            .push_bind(query.limit)
+            .push(" offset ")
+            .push_bind(query.offset);
+
+        let records = page
+            .build_query_as::<(String, Value, String)>()
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(id, fields, created_time)| Record {
+                id,
+                fields,
+                created_time,
+            })
+            .collect();
+
+        Ok(RecordPage {
+            records,
+            total,
+            matched,
+        })
+    }
+
+    pub async fn fetch_record_changes(
+        &self,
+        datasource_view_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<RecordChanges> {
+        let changes = sqlx::query_as::<_, RecordChange>(
+            "select id, created_at, updated_at, datasource_view_id, job_id, source_record_id, change_type, field_changes
+             from datasource_record_changes
+             where datasource_view_id = $1 and ($2::timestamptz is null or created_at >= $2)
+             order by created_at, source_record_id",
+        )
+        .bind(datasource_view_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(changes)
+    }
+
+    // Returns a view's current records in their source order, or `None` if the view has never been imported.
+    pub async fn fetch_datasource_records(&self, datasource_view_id: Uuid) -> Result<Option<Vec<Record<Value>>>> {
+        let imported = sqlx::query_as::<_, (Option<DateTime<Utc>>,)>(
+            "select last_imported_at from datasource_views where id = $1",
+        )
+        .bind(datasource_view_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((Some(_),)) = imported else {
+            return Ok(None);
+        };
+
+        let records = sqlx::query_as::<_, (String, Value, String)>(
+            "select source_record_id, fields, source_created_time from datasource_records
+             where datasource_view_id = $1 and removed_at is null
+             order by position",
+        )
+        .bind(datasource_view_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let records = records
+            .into_iter()
+            .map(|(id, fields, created_time)| Record {
+                id,
+                fields,
+                created_time,
+            })
+            .collect();
+
+        Ok(Some(records))
+    }
+}
+
+async fn insert_record_changes(
+    txn: &mut Transaction<'_, Postgres>,
+    datasource_view_id: Uuid,
+    job_id: Option<Uuid>,
+    changes: &[CreateRecordChange],
+) -> Result<()> {
+    for chunk in changes.chunks(Sql::RECORDS_PER_UPSERT) {
+        QueryBuilder::<Postgres>::new(
+            "insert into datasource_record_changes (datasource_view_id, job_id, source_record_id, change_type,
+             field_changes) ",
+        )
+        .push_values(chunk, |mut b, c| {
+            b.push_bind(datasource_view_id)
+                .push_bind(job_id)
+                .push_bind(&c.source_record_id)
+                .push_bind(c.change_type)
+                .push_bind(&c.field_changes);
+        })
+        .build()
+        .execute(&mut **txn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+// serde_json keeps object keys sorted, so equal fields always serialize, and hash, the same way
+pub fn content_hash(fields: &Value) -> String {
+    format!("{:x}", Sha256::digest(fields.to_string().as_bytes()))
+}
+
+// Pushes the query's filters and search as one boolean expression, `true` when there are none.
+fn push_record_conditions(builder: &mut QueryBuilder<'_, Postgres>, query: &RecordQuery) {
+    builder.push("true");
+
+    for filter in &query.filters {
+        let field = filter.field.clone();
+        match &filter.condition {
+            FilterCondition::Equals(value) => {
+                builder
+                    .push(" and (fields ->> ")
+                    .push_bind(field.clone())
+                    .push(" = ")
+                    .push_bind(value.clone())
+                    .push(" or (jsonb_typeof(fields -> ")
+                    .push_bind(field.clone())
+                    .push(") = 'array' and exists (select 1 from jsonb_array_elements_text(fields -> ")
+                    .push_bind(field)
+                    .push(") e where e = ")
+                    .push_bind(value.clone())
+                    .push(")))");
+            }
+            FilterCondition::Contains(value) => {
+                builder
+                    .push(" and fields ->> ")
+                    .push_bind(field)
+                    .push(" ilike ")
+                    .push_bind(like_pattern(value));
+            }
+            FilterCondition::IsEmpty(empty) => {
+                builder
+                    .push(if *empty { " and " } else { " and not " })
+                    .push("coalesce(fields -> ")
+                    .push_bind(field)
+                    .push(" in ('null', '\"\"', '[]'), true)");
+            }
+        }
+    }
+
+    if let Some(ref search) = query.search {
+        builder
+            .push(" and exists (select 1 from jsonb_each_text(fields) f where f.value ilike ")
+            .push_bind(like_pattern(search))
+            .push(")");
+    }
+}
+
+// a case-insensitive substring pattern for `ilike`, with the value's own wildcards escaped
+fn like_pattern(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{escaped}%")
 }
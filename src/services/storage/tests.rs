@@ -0,0 +1,284 @@
+use chrono::{TimeZone, Utc};
+
+use serde_json::json;
+
+use crate::services::{
+    airtable::{record::Record, schema::Schema},
+    storage::{
+        diff_records,
+        sql::content_hash,
+        types::{AirtableDatasourceViewMetadata, FileUploadDatasourceViewMetadata, RecordChangeType, RefreshSchedule},
+    },
+};
+
+#[test]
+pub fn test_refresh_schedule_next_after() {
+    let now = Utc.with_ymd_and_hms(2024, 5, 9, 10, 30, 0).unwrap();
+
+    let daily = RefreshSchedule::Cron("0 6 * * *".into());
+    assert_eq!(
+        daily.next_after(now).unwrap(),
+        Utc.with_ymd_and_hms(2024, 5, 10, 6, 0, 0).unwrap()
+    );
+
+    let hourly = RefreshSchedule::IntervalSeconds(3600);
+    assert_eq!(
+        hourly.next_after(now).unwrap(),
+        Utc.with_ymd_and_hms(2024, 5, 9, 11, 30, 0).unwrap()
+    );
+}
+
+#[test]
+pub fn test_refresh_schedule_validate() {
+    assert!(RefreshSchedule::Cron("0 0 6 * * *".into()).validate().is_ok());
+    assert!(RefreshSchedule::Cron("every morning".into()).validate().is_err());
+    assert!(RefreshSchedule::IntervalSeconds(10).validate().is_err());
+    assert!(RefreshSchedule::IntervalSeconds(u64::MAX).validate().is_err());
+    // a seven field expression whose only run is in the past
+    assert!(RefreshSchedule::Cron("0 0 6 1 1 * 2020".into()).validate().is_err());
+}
+
+#[test]
+pub fn test_refresh_schedule_next_after_out_of_range() {
+    let now = Utc.with_ymd_and_hms(2024, 5, 9, 10, 30, 0).unwrap();
+
+    assert!(RefreshSchedule::IntervalSeconds(u64::MAX).next_after(now).is_err());
+    assert!(RefreshSchedule::IntervalSeconds(i64::MAX as u64)
+        .next_after(now)
+        .is_err());
+}
+
+#[test]
+pub fn test_record_content_hash() {
+    let fields = json!({"First Name": "Ada", "Email": "ada@example.com"});
+    let reordered = serde_json::from_str(r#"{"Email": "ada@example.com", "First Name": "Ada"}"#).unwrap();
+
+    assert_eq!(content_hash(&fields), content_hash(&reordered));
+    assert_ne!(content_hash(&fields), content_hash(&json!({"First Name": "Ada"})));
+    assert_eq!(content_hash(&fields).len(), 64);
+}
+
+#[test]
+pub fn test_diff_records() {
+    let record = |id: &str, fields: serde_json::Value| Record {
+        id: id.into(),
+        fields,
+        created_time: "2024-05-01T00:00:00.000Z".into(),
+    };
+
+    let previous = vec![
+        record("rec1", json!({"Name": "Ada", "Email": "ada@example.com"})),
+        record("rec2", json!({"Name": "Grace", "Cohort": "Spring"})),
+        record("rec3", json!({"Name": "Alan"})),
+    ];
+    let current = vec![
+        record("rec1", json!({"Name": "Ada", "Email": "ada@example.com"})),
+        record("rec2", json!({"Name": "Grace", "Email": "grace@example.com"})),
+        record("rec4", json!({"Name": "Katherine"})),
+    ];
+
+    let changes = diff_records(&previous, &current);
+
+    assert_eq!(changes.len(), 3);
+
+    assert_eq!(changes[0].source_record_id, "rec2");
+    assert_eq!(changes[0].change_type, RecordChangeType::Modified);
+    assert_eq!(
+        changes[0].field_changes,
+        json!({
+            "Cohort": {"before": "Spring", "after": null},
+            "Email": {"before": null, "after": "grace@example.com"}
+        })
+    );
+
+    assert_eq!(changes[1].source_record_id, "rec4");
+    assert_eq!(changes[1].change_type, RecordChangeType::Added);
+    assert_eq!(
+        changes[1].field_changes,
+        json!({"Name": {"before": null, "after": "Katherine"}})
+    );
+
+    assert_eq!(changes[2].source_record_id, "rec3");
+    assert_eq!(changes[2].change_type, RecordChangeType::Removed);
+    assert_eq!(
+        changes[2].field_changes,
+        json!({"Name": {"before": "Alan", "after": null}})
+    );
+
+    assert!(diff_records(&current, &current).is_empty());
+}
+
+fn schema() -> Schema {
+    serde_json::from_value(json!({
+        "tables": [{
+            "id": "tblPeople",
+            "primaryFieldId": "fldFirst",
+            "name": "People",
+            "fields": [
+                {"id": "fldFirst", "name": "First Name", "type": "singleLineText"},
+                {"id": "fldLast", "name": "Last Name", "type": "singleLineText"},
+                {"id": "fldEmail", "name": "Email", "type": "email"},
+                {"id": "fldWork", "name": "Work Email", "type": "email"},
+                {"id": "fldTags", "name": "Tags", "type": "multipleSelects", "options": {"choices": []}},
+                {"id": "fldOrg", "name": "Organization", "type": "multipleRecordLinks", "options": {
+                    "linkedTableId": "tblOrgs", "isReversed": false, "prefersSingleRecordLink": true
+                }}
+            ],
+            "views": [{"id": "viwGrid", "name": "Grid view", "type": "grid"}]
+        }, {
+            "id": "tblOrgs",
+            "primaryFieldId": "fldOrgName",
+            "name": "Organizations",
+            "fields": [{"id": "fldOrgName", "name": "Org Name", "type": "singleLineText"}],
+            "views": []
+        }]
+    }))
+    .unwrap()
+}
+
+fn metadata(value: serde_json::Value) -> AirtableDatasourceViewMetadata {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+pub fn test_resolve_metadata_to_field_ids() {
+    let resolved = metadata(json!({
+        "isUserTable": true,
+        "firstNameColumn": "First Name",
+        "lastNameColumn": "fldLast",
+        "emailColumn": "Email",
+        "base": "app1",
+        "table": "People",
+        "view": "Grid view",
+        "fields": ["First Name", "Last Name", "Email"],
+        "writeBack": {"emailColumn": "Work Email"}
+    }))
+    .resolve(&schema())
+    .unwrap();
+
+    assert_eq!(resolved.table, "tblPeople");
+    assert_eq!(resolved.view, "viwGrid");
+    assert_eq!(resolved.fields, ["fldFirst", "fldLast", "fldEmail"]);
+    assert_eq!(resolved.first_name_column.as_deref(), Some("fldFirst"));
+    assert_eq!(resolved.email_column.as_deref(), Some("fldEmail"));
+    assert_eq!(resolved.write_back.unwrap().email_column, "fldWork");
+    assert_eq!(resolved.field_names["fldLast"], "Last Name");
+}
+
+#[test]
+pub fn test_resolve_metadata_reports_every_problem() {
+    let problems = metadata(json!({
+        "isUserTable": true,
+        "firstNameColumn": "Tags",
+        "emailColumn": "Email",
+        "base": "app1",
+        "table": "People",
+        "view": "Kanban",
+        "fields": ["Tags", "Phone"]
+    }))
+    .resolve(&schema())
+    .unwrap_err();
+
+    assert_eq!(
+        problems,
+        [
+            "table People has no view Kanban",
+            "table People has no field Phone",
+            "field Tags is a multipleSelects field, expected a text field",
+            "a user table needs a last name column",
+            "the email column Email is not one of the fields",
+        ]
+    );
+
+    let problems = metadata(json!({
+        "isUserTable": false,
+        "base": "app1",
+        "table": "Projects",
+        "view": "Grid view",
+        "fields": []
+    }))
+    .resolve(&schema())
+    .unwrap_err();
+
+    assert_eq!(problems, ["base app1 has no table Projects"]);
+}
+
+#[test]
+pub fn test_resolve_link_expansions() {
+    let view = |expansion: serde_json::Value| {
+        metadata(json!({
+            "isUserTable": false,
+            "base": "app1",
+            "table": "People",
+            "view": "Grid view",
+            "fields": ["First Name", "Organization", "Tags"],
+            "linkExpansions": [expansion]
+        }))
+        .resolve(&schema())
+    };
+
+    let resolved = view(json!({"field": "Organization", "displayField": "Org Name"})).unwrap();
+    let expansion = &resolved.link_expansions[0];
+    assert_eq!(expansion.field, "fldOrg");
+    assert_eq!(expansion.linked_table.as_deref(), Some("tblOrgs"));
+    assert_eq!(expansion.display_field, "fldOrgName");
+
+    assert_eq!(
+        view(json!({"field": "Tags", "displayField": "Org Name"})).unwrap_err(),
+        ["field Tags does not link to another table"]
+    );
+    assert_eq!(
+        view(json!({"field": "Organization", "linkedTable": "People", "displayField": "Org Name"})).unwrap_err(),
+        ["field Organization links to Organizations, not People"]
+    );
+    assert_eq!(
+        view(json!({"field": "Organization", "displayField": "Name"})).unwrap_err(),
+        ["table Organizations has no field Name"]
+    );
+}
+
+#[test]
+pub fn test_source_differs() {
+    let current = metadata(json!({
+        "isUserTable": true,
+        "emailColumn": "fldEmail",
+        "base": "app1",
+        "table": "tblPeople",
+        "view": "viwGrid",
+        "fields": ["fldFirst", "fldEmail"]
+    }));
+
+    let mut edited = current.clone();
+    edited.email_column = Some("fldWork".into());
+    edited.field_names.insert("fldFirst".into(), "First".into());
+    assert!(!current.source_differs(&edited));
+
+    let mut edited = current.clone();
+    edited.fields.push("fldLast".into());
+    assert!(current.source_differs(&edited));
+
+    let mut edited = current.clone();
+    edited.filter_formula = Some("{Status} = 'Active'".into());
+    assert!(current.source_differs(&edited));
+}
+
+#[test]
+pub fn test_upload_metadata_columns_must_be_in_the_file() {
+    let mut metadata = serde_json::from_value::<FileUploadDatasourceViewMetadata>(json!({
+        "fileName": "roster.csv",
+        "format": "csv",
+        "delimiter": ",",
+        "hasHeaders": true,
+        "columns": ["First", "Last", "Email"],
+        "firstNameColumn": "First",
+        "emailColumn": "Email"
+    }))
+    .unwrap();
+    assert!(metadata.validate().is_ok());
+
+    metadata.user_columns.last_name_column = Some("Surname".into());
+    assert_eq!(
+        metadata.validate(),
+        Err(vec!["the last name column Surname is not in the file".to_owned()])
+    );
+}
@@ -1,12 +1,22 @@
-use anyhow::{bail, Error};
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::{anyhow, bail, Error};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::Type;
 
+use crate::services::{
+    airtable::schema::{ColumnKind, Field, FieldType, Schema, Table},
+    uploads::{UploadFormat, UserColumns},
+    workspace::users::ListUsersOptions,
+};
+
 #[derive(Debug, Serialize, Deserialize, Type, Copy, Clone)]
 #[sqlx(type_name = "supported_datasource", rename_all = "snake_case")]
 pub enum SupportedDatasource {
     Airtable,
     GoogleWorkspaceAdminDirectory,
+    FileUpload,
 }
 
 impl TryInto<SupportedDatasource> for String {
@@ -16,6 +26,7 @@ impl TryInto<SupportedDatasource> for String {
         match self.to_lowercase().as_str() {
             "airtable" => Ok(SupportedDatasource::Airtable),
             "google" => Ok(SupportedDatasource::GoogleWorkspaceAdminDirectory),
+            "upload" => Ok(SupportedDatasource::FileUpload),
             _ => bail!("unsupported datasource"),
         }
     }
@@ -49,3 +60,341 @@ pub enum JobType {
     ImportData,
     UndoExport,
 }
+
+#[derive(Debug, Serialize, Deserialize, Type, Copy, Clone, PartialEq, Eq)]
+#[sqlx(type_name = "job_item_status", rename_all = "snake_case")]
+pub enum JobItemStatus {
+    Success,
+    Failure,
+    Skipped,
+}
+
+#[derive(Debug, Serialize, Deserialize, Type, Copy, Clone, PartialEq, Eq)]
+#[sqlx(type_name = "record_change_type", rename_all = "snake_case")]
+pub enum RecordChangeType {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RefreshSchedule {
+    Cron(String),
+    IntervalSeconds(u64),
+}
+
+impl RefreshSchedule {
+    const MIN_INTERVAL_SECONDS: u64 = 300;
+    const MAX_INTERVAL_SECONDS: u64 = 366 * 24 * 60 * 60;
+
+    // accepts both the standard five field syntax and the cron crate's six/seven field syntax with seconds
+    fn cron_schedule(expression: &str) -> Result<cron::Schedule, Error> {
+        let expression = match expression.split_whitespace().count() {
+            5 => format!("0 {expression}"),
+            _ => expression.to_owned(),
+        };
+        Ok(cron::Schedule::from_str(&expression)?)
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        match self {
+            Self::Cron(expression) => {
+                Self::cron_schedule(expression)?;
+            }
+            Self::IntervalSeconds(seconds) if *seconds < Self::MIN_INTERVAL_SECONDS => {
+                bail!(
+                    "refresh interval must be at least {} seconds",
+                    Self::MIN_INTERVAL_SECONDS
+                )
+            }
+            Self::IntervalSeconds(seconds) if *seconds > Self::MAX_INTERVAL_SECONDS => {
+                bail!(
+                    "refresh interval must be at most {} seconds",
+                    Self::MAX_INTERVAL_SECONDS
+                )
+            }
+            Self::IntervalSeconds(_) => {}
+        };
+
+        // a schedule that never runs again is as useless as an invalid one
+        self.next_after(Utc::now())?;
+        Ok(())
+    }
+
+    pub fn next_after(&self, after: DateTime<Utc>) -> Result<DateTime<Utc>, Error> {
+        match self {
+            Self::Cron(expression) => match Self::cron_schedule(expression)?.after(&after).next() {
+                Some(next) => Ok(next),
+                None => bail!("cron expression has no upcoming runs"),
+            },
+            Self::IntervalSeconds(seconds) => i64::try_from(*seconds)
+                .ok()
+                .and_then(chrono::Duration::try_seconds)
+                .and_then(|interval| after.checked_add_signed(interval))
+                .ok_or_else(|| anyhow!("refresh interval of {seconds} seconds is out of range")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AirtableDatasourceViewMetadata {
+    pub is_user_table: bool,
+    pub first_name_column: Option<String>,
+    pub last_name_column: Option<String>,
+    pub email_column: Option<String>,
+    pub base: String,
+    pub table: String,
+    pub view: String,
+    pub fields: Vec<String>,
+    // an airtable formula applied on top of the view, e.g. `{Status} = 'Active'`
+    pub filter_formula: Option<String>,
+    pub write_back: Option<AirtableWriteBack>,
+    #[serde(default)]
+    pub link_expansions: Vec<AirtableLinkExpansion>,
+    // field ids to the names they had at the last import, since records are cached by name
+    #[serde(default)]
+    pub field_names: BTreeMap<String, String>,
+}
+
+// Columns on the source table that exports fill in with the generated email and the time of the export.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AirtableWriteBack {
+    pub email_column: String,
+    pub exported_at_column: Option<String>,
+}
+
+// Replaces the record ids in a link field with a field of the linked records, e.g. a project link with the projects'
+// names. The linked table is taken from the link field when it isn't given.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AirtableLinkExpansion {
+    pub field: String,
+    pub linked_table: Option<String>,
+    pub display_field: String,
+}
+
+impl AirtableDatasourceViewMetadata {
+    // Checks the table, view, fields and mapped columns against the base schema and swaps their names for ids, so
+    // the view keeps working when they are renamed in Airtable. Returns every problem found.
+    pub fn resolve(mut self, schema: &Schema) -> Result<Self, Vec<String>> {
+        let Some(table) = schema.table(&self.table) else {
+            return Err(vec![format!("base {} has no table {}", self.base, self.table)]);
+        };
+
+        let mut problems = Vec::new();
+
+        match table.view(&self.view) {
+            Some(view) => self.view = view.id.clone(),
+            None => problems.push(format!("table {} has no view {}", table.name, self.view)),
+        }
+
+        let fields = self
+            .fields
+            .iter()
+            .filter_map(|field| {
+                let found = table.field(field);
+                if found.is_none() {
+                    problems.push(format!("table {} has no field {field}", table.name));
+                }
+                found
+            })
+            .collect::<Vec<&Field>>();
+
+        let columns = [
+            ("first name", &mut self.first_name_column, ColumnKind::Text),
+            ("last name", &mut self.last_name_column, ColumnKind::Text),
+            ("email", &mut self.email_column, ColumnKind::Email),
+        ];
+
+        for (label, column, kind) in columns {
+            match column {
+                Some(name) => match table.validate_column(name, kind) {
+                    Ok(field) if fields.iter().any(|f| f.id == field.id) => *name = field.id.clone(),
+                    Ok(field) => problems.push(format!("the {label} column {} is not one of the fields", field.name)),
+                    Err(e) => problems.push(e.to_string()),
+                },
+                None if self.is_user_table => problems.push(format!("a user table needs a {label} column")),
+                None => {}
+            }
+        }
+
+        if let Some(ref mut write_back) = self.write_back {
+            let columns = std::iter::once(&mut write_back.email_column).chain(write_back.exported_at_column.as_mut());
+            for column in columns {
+                match table.field(column) {
+                    Some(field) => *column = field.id.clone(),
+                    None => problems.push(format!("table {} has no field {column}", table.name)),
+                }
+            }
+        }
+
+        for expansion in &mut self.link_expansions {
+            let field = match table.field(&expansion.field) {
+                Some(field) if fields.iter().any(|f| f.id == field.id) => field,
+                Some(field) => {
+                    problems.push(format!("the linked field {} is not one of the fields", field.name));
+                    continue;
+                }
+                None => {
+                    problems.push(format!("table {} has no field {}", table.name, expansion.field));
+                    continue;
+                }
+            };
+
+            let FieldType::MultipleRecordLinks(ref options) = field.field_type else {
+                problems.push(format!("field {} does not link to another table", field.name));
+                continue;
+            };
+
+            let Some(linked_table) = schema.table(&options.linked_table_id) else {
+                problems.push(format!("base {} has no table {}", self.base, options.linked_table_id));
+                continue;
+            };
+
+            if let Some(ref expected) = expansion.linked_table {
+                if *expected != linked_table.id && *expected != linked_table.name {
+                    problems.push(format!(
+                        "field {} links to {}, not {expected}",
+                        field.name, linked_table.name
+                    ));
+                    continue;
+                }
+            }
+
+            let Some(display_field) = linked_table.field(&expansion.display_field) else {
+                problems.push(format!(
+                    "table {} has no field {}",
+                    linked_table.name, expansion.display_field
+                ));
+                continue;
+            };
+
+            expansion.field = field.id.clone();
+            expansion.linked_table = Some(linked_table.id.clone());
+            expansion.display_field = display_field.id.clone();
+        }
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        self.table = table.id.clone();
+        self.fields = fields.iter().map(|f| f.id.clone()).collect();
+        self.refresh_field_names(table);
+
+        Ok(self)
+    }
+
+    // Updates the names of the view's fields from the table, returning whether any of them changed.
+    pub fn refresh_field_names(&mut self, table: &Table) -> bool {
+        let field_names = self
+            .fields
+            .iter()
+            .filter_map(|id| table.field(id).map(|f| (id.clone(), f.name.clone())))
+            .collect::<BTreeMap<String, String>>();
+
+        let changed = field_names != self.field_names;
+        self.field_names = field_names;
+        changed
+    }
+
+    // The name a field is cached under; views created before fields were stored by id hold the name itself.
+    pub fn field_name<'a>(&'a self, field: &'a str) -> &'a str {
+        self.field_names.get(field).map_or(field, String::as_str)
+    }
+
+    // Webhook payloads don't say whether a changed record still matches the filter formula, so a filtered view is
+    // imported again instead of having its cached records patched.
+    pub fn patch_from_webhooks(&self) -> bool {
+        self.filter_formula.is_none()
+    }
+
+    // Whether importing with `other` would fetch different records or fields, so records cached for this metadata
+    // no longer apply. Column mappings and write back only matter to exports.
+    pub fn source_differs(&self, other: &Self) -> bool {
+        self.base != other.base
+            || self.table != other.table
+            || self.view != other.view
+            || self.fields != other.fields
+            || self.filter_formula != other.filter_formula
+            || self.link_expansions != other.link_expansions
+    }
+}
+
+// The part of the Workspace directory a view imports, listed as the admin who created the view.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDatasourceViewMetadata {
+    #[serde(flatten)]
+    pub filter: ListUsersOptions,
+    // set from the signed in user rather than the request
+    #[serde(default)]
+    pub admin_email: String,
+}
+
+impl WorkspaceDatasourceViewMetadata {
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = vec![];
+
+        if let Some(ref domain) = self.filter.domain {
+            if domain.trim().is_empty() || domain.contains('@') {
+                problems.push(format!("{domain} is not a domain"));
+            }
+        }
+        if let Some(ref path) = self.filter.org_unit_path {
+            if !path.starts_with('/') {
+                problems.push(format!(
+                    "the organizational unit {path} should be a path starting with /"
+                ));
+            }
+        }
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(problems),
+        }
+    }
+}
+
+// A CSV or XLSX roster whose rows were stored when it was uploaded. Only the user column mapping can change later.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileUploadDatasourceViewMetadata {
+    pub file_name: String,
+    pub format: UploadFormat,
+    pub delimiter: Option<char>,
+    pub has_headers: bool,
+    pub columns: Vec<String>,
+    #[serde(flatten)]
+    pub user_columns: UserColumns,
+}
+
+impl FileUploadDatasourceViewMetadata {
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let UserColumns {
+            first_name_column,
+            last_name_column,
+            email_column,
+        } = &self.user_columns;
+
+        let problems = [
+            ("first name", first_name_column),
+            ("last name", last_name_column),
+            ("email", email_column),
+        ]
+        .into_iter()
+        .filter_map(|(label, column)| {
+            let column = column.as_ref()?;
+            (!self.columns.contains(column)).then(|| format!("the {label} column {column} is not in the file"))
+        })
+        .collect::<Vec<String>>();
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(problems),
+        }
+    }
+}
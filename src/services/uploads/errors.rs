@@ -0,0 +1,19 @@
+use std::fmt;
+
+// Returned when an uploaded file can't be read as a roster, with a reason that can be shown to whoever uploaded it.
+#[derive(Debug)]
+pub struct InvalidUpload {
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl std::error::Error for InvalidUpload {}
+
+pub(super) fn invalid(reason: impl Into<String>) -> anyhow::Error {
+    InvalidUpload { reason: reason.into() }.into()
+}
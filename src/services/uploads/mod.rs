@@ -0,0 +1,301 @@
+pub mod errors;
+#[cfg(test)]
+mod tests;
+
+use std::{collections::HashSet, io::Cursor};
+
+use anyhow::{bail, Result};
+use calamine::{open_workbook_from_rs, Data, DataType, Reader, Xlsx};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use self::errors::invalid;
+use super::airtable::record::Record;
+
+const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+// how many lines are compared when guessing a csv file's delimiter
+const DELIMITER_SAMPLE_ROWS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadFormat {
+    Csv,
+    Xlsx,
+}
+
+impl UploadFormat {
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        match extension.to_lowercase().as_str() {
+            "csv" | "tsv" | "txt" => Some(UploadFormat::Csv),
+            "xlsx" => Some(UploadFormat::Xlsx),
+            _ => None,
+        }
+    }
+}
+
+// Overrides for what would otherwise be detected from the file.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub delimiter: Option<u8>,
+    pub has_headers: Option<bool>,
+}
+
+// The columns holding users' names and emails, by column name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserColumns {
+    pub first_name_column: Option<String>,
+    pub last_name_column: Option<String>,
+    pub email_column: Option<String>,
+}
+
+impl UserColumns {
+    // Applies an edit that only names the columns it changes, where a null unmaps a column.
+    pub fn edited(&self, edits: Value) -> Result<Self> {
+        let Value::Object(edits) = edits else {
+            bail!("user columns must be an object");
+        };
+
+        let mut columns = serde_json::to_value(self)?;
+        if let Value::Object(ref mut columns) = columns {
+            columns.extend(edits);
+        }
+        Ok(serde_json::from_value(columns)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedUpload {
+    // only set for csv files
+    pub delimiter: Option<char>,
+    pub has_headers: bool,
+    pub columns: Vec<String>,
+    pub rows: Vec<UploadRow>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadRow {
+    // the row's number in the file, counting from 1
+    pub number: usize,
+    pub cells: Vec<String>,
+}
+
+// Reads the rows of a csv file or of the first sheet of a workbook. Blank rows are dropped, and short rows are padded
+// so every row has a cell for each column.
+pub fn parse_upload(format: UploadFormat, bytes: &[u8], options: &ParseOptions) -> Result<ParsedUpload> {
+    let (delimiter, mut rows) = match format {
+        UploadFormat::Csv => {
+            let text = std::str::from_utf8(bytes)
+                .map_err(|_| invalid("the file is not UTF-8 text, export it as CSV UTF-8 and upload it again"))?;
+            let text = text.trim_start_matches('\u{feff}');
+            let delimiter = options.delimiter.unwrap_or_else(|| detect_delimiter(text));
+            (Some(delimiter as char), read_csv(text, delimiter)?)
+        }
+        UploadFormat::Xlsx => (None, read_xlsx(bytes)?),
+    };
+
+    rows.retain(|row| row.cells.iter().any(|cell| !cell.is_empty()));
+    if rows.is_empty() {
+        return Err(invalid("the file has no rows"));
+    }
+
+    let width = rows.iter().map(|row| row.cells.len()).max().unwrap_or_default();
+    for row in rows.iter_mut() {
+        row.cells.resize(width, String::new());
+    }
+
+    let has_headers = options.has_headers.unwrap_or_else(|| detect_headers(&rows[0].cells));
+    let columns = match has_headers {
+        true => column_names(&rows.remove(0).cells),
+        false => (1..=width).map(|i| format!("Column {i}")).collect(),
+    };
+
+    Ok(ParsedUpload {
+        delimiter,
+        has_headers,
+        columns,
+        rows,
+    })
+}
+
+impl ParsedUpload {
+    // Stores each row by column name, keyed by its row number in the file so problems can be traced back to it.
+    pub fn records(&self, created_time: &str) -> Vec<Record<Value>> {
+        self.rows
+            .iter()
+            .map(|row| Record {
+                id: format!("row-{}", row.number),
+                created_time: created_time.to_owned(),
+                fields: self
+                    .columns
+                    .iter()
+                    .cloned()
+                    .zip(row.cells.iter().map(|cell| Value::from(cell.as_str())))
+                    .collect::<Map<String, Value>>()
+                    .into(),
+            })
+            .collect()
+    }
+
+    // Guesses the name and email columns from the headers, falling back to the column of email addresses for emails.
+    pub fn guess_user_columns(&self) -> UserColumns {
+        let by_header = |aliases: &[&str]| {
+            self.columns
+                .iter()
+                .find(|c| aliases.contains(&normalize(c).as_str()))
+                .cloned()
+        };
+
+        let by_values = || {
+            self.columns.iter().enumerate().find_map(|(i, column)| {
+                let values = self.rows.iter().map(|r| &r.cells[i]).filter(|v| !v.is_empty());
+                let (emails, total) = values.fold((0, 0), |(e, t), v| (e + looks_like_email(v) as usize, t + 1));
+                (total > 0 && emails * 2 > total).then(|| column.clone())
+            })
+        };
+
+        UserColumns {
+            first_name_column: by_header(&["firstname", "givenname", "first", "fname", "preferredfirstname"]),
+            last_name_column: by_header(&["lastname", "familyname", "surname", "last", "lname"]),
+            email_column: by_header(&["email", "emailaddress", "personalemail", "mail"]).or_else(by_values),
+        }
+    }
+}
+
+// Picks the delimiter that splits the most of the first lines into as many cells as the first line, and then the one
+// giving the most cells.
+fn detect_delimiter(text: &str) -> u8 {
+    DELIMITERS
+        .iter()
+        .filter_map(|&delimiter| {
+            let widths = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .delimiter(delimiter)
+                .from_reader(text.as_bytes())
+                .records()
+                .take(DELIMITER_SAMPLE_ROWS)
+                .map(|r| r.map(|r| r.len()))
+                .collect::<Result<Vec<usize>, _>>()
+                .ok()?;
+
+            let width = *widths.first().filter(|w| **w > 1)?;
+            let matching = widths.iter().filter(|w| **w == width).count();
+            Some(((matching, width), delimiter))
+        })
+        // of equal scores `max_by_key` keeps the last, so the candidates are walked backwards to prefer the earlier
+        .rev()
+        .max_by_key(|(score, _)| *score)
+        .map_or(b',', |(_, delimiter)| delimiter)
+}
+
+// Rosters almost always have a header row, so the first row is only taken as data when it holds an email address or
+// a number, which headers don't.
+fn detect_headers(first_row: &[String]) -> bool {
+    !first_row
+        .iter()
+        .any(|cell| looks_like_email(cell) || cell.parse::<f64>().is_ok())
+}
+
+// Names blank headers by position and tells repeated ones apart, since records are keyed by column name.
+fn column_names(headers: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            let base = match header.is_empty() {
+                true => format!("Column {}", i + 1),
+                false => header.clone(),
+            };
+            let mut name = base.clone();
+            let mut n = 2;
+            while !seen.insert(name.clone()) {
+                name = format!("{base} ({n})");
+                n += 1;
+            }
+            name
+        })
+        .collect()
+}
+
+fn read_csv(text: &str, delimiter: u8) -> Result<Vec<UploadRow>> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(text.as_bytes())
+        .records()
+        .enumerate()
+        .map(|(i, record)| {
+            let record = record.map_err(|e| invalid(format!("the file is not valid CSV: {e}")))?;
+            Ok(UploadRow {
+                number: record.position().map_or(i + 1, |p| line_at(text, p.byte() as usize)),
+                cells: record.iter().map(|cell| cell.trim().to_owned()).collect(),
+            })
+        })
+        .collect()
+}
+
+// The line a record starts on. The reader neither counts the blank lines it skips nor includes them in the record's
+// position, so they are counted here.
+fn line_at(text: &str, byte: usize) -> usize {
+    let rest = text.get(byte..).unwrap_or_default();
+    let blank = rest.len() - rest.trim_start_matches(['\r', '\n']).len();
+
+    text.get(..byte + blank).unwrap_or(text).matches('\n').count() + 1
+}
+
+fn read_xlsx(bytes: &[u8]) -> Result<Vec<UploadRow>> {
+    let mut workbook = open_workbook_from_rs::<Xlsx<_>, _>(Cursor::new(bytes))
+        .map_err(|e| invalid(format!("the file is not a valid XLSX workbook: {e}")))?;
+
+    let sheet = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| invalid("the workbook has no sheets"))?
+        .map_err(|e| invalid(format!("the first sheet could not be read: {e}")))?;
+
+    // the range starts at the first cell in use, which isn't always A1
+    let first_row = sheet.start().map_or(0, |(row, _)| row as usize);
+
+    Ok(sheet
+        .rows()
+        .enumerate()
+        .map(|(i, row)| UploadRow {
+            number: first_row + i + 1,
+            cells: row.iter().map(cell_text).collect(),
+        })
+        .collect())
+}
+
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(s) => s.trim().to_owned(),
+        // whole numbers such as phone numbers or ids are stored as floats
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => format!("{}", *f as i64),
+        Data::DateTime(_) => match cell.as_datetime() {
+            Some(datetime) if datetime.time() == chrono::NaiveTime::MIN => datetime.date().to_string(),
+            Some(datetime) => datetime.to_string(),
+            None => cell.to_string(),
+        },
+        _ => cell.to_string(),
+    }
+}
+
+fn looks_like_email(value: &str) -> bool {
+    match value.trim().split_once('@') {
+        Some((user, domain)) => !user.is_empty() && domain.contains('.') && !value.contains(' '),
+        None => false,
+    }
+}
+
+fn normalize(header: &str) -> String {
+    header
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
@@ -0,0 +1,140 @@
+use serde_json::json;
+
+use super::{parse_upload, ParseOptions, UploadFormat, UserColumns};
+
+#[test]
+pub fn test_upload_format_from_file_name() {
+    assert_eq!(UploadFormat::from_file_name("roster.CSV"), Some(UploadFormat::Csv));
+    assert_eq!(
+        UploadFormat::from_file_name("roster.2024.xlsx"),
+        Some(UploadFormat::Xlsx)
+    );
+    assert_eq!(UploadFormat::from_file_name("roster.xls"), None);
+    assert_eq!(UploadFormat::from_file_name("roster"), None);
+}
+
+#[test]
+pub fn test_parse_csv_detects_delimiter_and_headers() {
+    let csv =
+        "\u{feff}First Name;Last Name;E-mail\nAda;Lovelace;ada@example.org\n\n\"Hopper, Grace\";;grace@example.org\n";
+
+    let upload = parse_upload(UploadFormat::Csv, csv.as_bytes(), &ParseOptions::default()).unwrap();
+
+    assert_eq!(upload.delimiter, Some(';'));
+    assert!(upload.has_headers);
+    assert_eq!(upload.columns, ["First Name", "Last Name", "E-mail"]);
+    assert_eq!(upload.rows.len(), 2);
+    assert_eq!(upload.rows[1].cells, ["Hopper, Grace", "", "grace@example.org"]);
+
+    let records = upload.records("2024-05-28T00:00:00Z");
+    assert_eq!(records[0].id, "row-2");
+    assert_eq!(records[1].id, "row-4");
+    assert_eq!(
+        records[0].fields,
+        json!({"First Name": "Ada", "Last Name": "Lovelace", "E-mail": "ada@example.org"})
+    );
+
+    assert_eq!(
+        upload.guess_user_columns(),
+        UserColumns {
+            first_name_column: Some("First Name".into()),
+            last_name_column: Some("Last Name".into()),
+            email_column: Some("E-mail".into()),
+        }
+    );
+}
+
+#[test]
+pub fn test_parse_csv_without_headers() {
+    let csv = "Ada\tLovelace\tada@example.org\nGrace\tHopper\tgrace@example.org\tsometimes\n";
+
+    let upload = parse_upload(UploadFormat::Csv, csv.as_bytes(), &ParseOptions::default()).unwrap();
+
+    assert_eq!(upload.delimiter, Some('\t'));
+    assert!(!upload.has_headers);
+    assert_eq!(upload.columns, ["Column 1", "Column 2", "Column 3", "Column 4"]);
+    assert_eq!(upload.rows[0].cells[3], "");
+
+    // without headers only the emails can be recognized
+    let columns = upload.guess_user_columns();
+    assert_eq!(columns.first_name_column, None);
+    assert_eq!(columns.email_column.as_deref(), Some("Column 3"));
+}
+
+#[test]
+pub fn test_parse_csv_with_overrides() {
+    let csv = "Name,Email,Name,\nAda,ada@example.org,Lovelace,x\n";
+    let options = ParseOptions {
+        delimiter: Some(b','),
+        has_headers: Some(true),
+    };
+
+    let upload = parse_upload(UploadFormat::Csv, csv.as_bytes(), &options).unwrap();
+
+    // repeated and blank headers still give distinct field names
+    assert_eq!(upload.columns, ["Name", "Email", "Name (2)", "Column 4"]);
+}
+
+#[test]
+pub fn test_parse_rejects_unreadable_files() {
+    let options = ParseOptions::default();
+
+    let latin1 = parse_upload(UploadFormat::Csv, b"Nombre\nJos\xe9\n", &options).unwrap_err();
+    assert!(latin1.to_string().contains("UTF-8"));
+
+    let empty = parse_upload(UploadFormat::Csv, b"\n,,\n", &options).unwrap_err();
+    assert_eq!(empty.to_string(), "the file has no rows");
+
+    let not_xlsx = parse_upload(UploadFormat::Xlsx, b"First,Last\n", &options).unwrap_err();
+    assert!(not_xlsx
+        .to_string()
+        .starts_with("the file is not a valid XLSX workbook"));
+}
+
+#[test]
+pub fn test_parse_xlsx() {
+    let xlsx = include_bytes!("fixtures/roster.xlsx");
+
+    let upload = parse_upload(UploadFormat::Xlsx, xlsx, &ParseOptions::default()).unwrap();
+
+    assert_eq!(upload.delimiter, None);
+    assert_eq!(
+        upload.columns,
+        ["First Name", "Last Name", "Email Address", "Hours", "Joined"]
+    );
+    assert_eq!(
+        upload.rows[0].cells,
+        ["Ada", "Lovelace", "ada@example.org", "12", "2024-05-01"]
+    );
+    assert_eq!(upload.rows[1].number, 4);
+    assert_eq!(upload.rows[1].cells[3], "7.5");
+    assert_eq!(
+        upload.guess_user_columns().email_column.as_deref(),
+        Some("Email Address")
+    );
+}
+
+#[test]
+pub fn test_edit_user_columns() {
+    let columns = UserColumns {
+        first_name_column: Some("First".into()),
+        last_name_column: Some("Last".into()),
+        email_column: None,
+    };
+
+    let edited = columns
+        .edited(json!({"emailColumn": "Email", "lastNameColumn": null}))
+        .unwrap();
+    assert_eq!(
+        edited,
+        UserColumns {
+            first_name_column: Some("First".into()),
+            last_name_column: None,
+            email_column: Some("Email".into()),
+        }
+    );
+
+    assert_eq!(columns.edited(json!({})).unwrap(), columns);
+    assert!(columns.edited(json!(["Email"])).is_err());
+    assert!(columns.edited(json!({"emailColumn": 3})).is_err());
+}
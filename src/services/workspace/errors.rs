@@ -0,0 +1,15 @@
+use std::fmt;
+
+// Returned when the directory has no account for the requested user.
+#[derive(Debug)]
+pub struct UserNotFound {
+    pub email: String,
+}
+
+impl fmt::Display for UserNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "workspace user {} does not exist", self.email)
+    }
+}
+
+impl std::error::Error for UserNotFound {}
@@ -1,14 +1,18 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
-use self::users::{CreateWorkspaceUser, WorkspaceUserData};
+use self::users::{CreateWorkspaceUser, ListUsersOptions, WorkspaceUsers};
 
+pub mod errors;
 pub mod service_account;
+#[cfg(test)]
+mod tests;
 pub mod users;
 
 #[async_trait]
 pub trait WorkspaceClient: Send + Sync {
-    async fn list_users(&self, impersonate: &str) -> Result<reqwest::StatusCode>;
+    // Lists every user matching `options`, following the listing's pages.
+    async fn list_users(&self, impersonate: &str, options: &ListUsersOptions) -> Result<WorkspaceUsers>;
     async fn create_user(&self, impersonate: &str, user: CreateWorkspaceUser) -> Result<()>;
     async fn delete_user(&self, impersonate: &str, user: &str) -> Result<()>;
 }
@@ -1,8 +1,9 @@
 use super::{
-    users::{CreateWorkspaceUser, WorkspaceUser, WorkspaceUserData},
+    errors::UserNotFound,
+    users::{CreateWorkspaceUser, ListUsersOptions, WorkspaceUserData, WorkspaceUsers},
     WorkspaceClient,
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use axum::async_trait;
 use chrono::Utc;
 use jsonwebtoken::{Algorithm, EncodingKey, Header};
@@ -99,13 +100,50 @@ impl ServiceAccountWorkspaceClient {
 
 #[async_trait]
 impl WorkspaceClient for ServiceAccountWorkspaceClient {
-    async fn list_users(&self, impersonate: &str) -> Result<reqwest::StatusCode> {
-        let res = self.http.get("https://google.com").send().await?;
-        // let access_token = self.get_access_token(
-        //     impersonate,
-        //     "https://www.googleapis.com/auth/admin.directory.user.readonly",
-        // );
-        Ok(res.status())
+    async fn list_users(&self, impersonate: &str, options: &ListUsersOptions) -> Result<WorkspaceUsers> {
+        let access_token = self
+            .get_access_token(
+                impersonate,
+                "https://www.googleapis.com/auth/admin.directory.user.readonly",
+            )
+            .await?;
+
+        let auth_header = format!("Bearer {access_token}");
+        let url = "https://admin.googleapis.com/admin/directory/v1/users";
+
+        let mut users = vec![];
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let res = self
+                .http
+                .get(url)
+                .header("Authorization", &auth_header)
+                .query(&options.query_params(page_token.as_deref()))
+                .send()
+                .await
+                .context("list workspace users")?;
+
+            let status = res.status();
+            if !status.is_success() {
+                let body = res.text().await.unwrap_or_default();
+                bail!("list workspace users: {status}: {body}");
+            }
+
+            let page = res
+                .json::<WorkspaceUserData>()
+                .await
+                .context("deserialize workspace users")?;
+
+            users.extend(page.users);
+
+            match page.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+
+        Ok(users)
     }
 
     async fn create_user(&self, impersonate: &str, user: CreateWorkspaceUser) -> Result<()> {
@@ -116,13 +154,20 @@ impl WorkspaceClient for ServiceAccountWorkspaceClient {
         let auth_header = format!("Bearer {access_token}");
         let url = "https://admin.googleapis.com/admin/directory/v1/users";
 
-        let _ = self
+        let res = self
             .http
             .post(url)
             .header("Authorization", auth_header)
             .json(&user)
             .send()
-            .await?;
+            .await
+            .context("create workspace user")?;
+
+        let status = res.status();
+        if !status.is_success() {
+            let body = res.text().await.unwrap_or_default();
+            bail!("create workspace user {}: {status}: {body}", user.primary_email);
+        }
 
         Ok(())
     }
@@ -135,12 +180,22 @@ impl WorkspaceClient for ServiceAccountWorkspaceClient {
         let auth_header = format!("Bearer {access_token}");
         let url = format!("https://admin.googleapis.com/admin/directory/v1/users/{user}");
 
-        let _ = self
+        let res = self
             .http
             .delete(url)
             .header("Authorization", auth_header)
             .send()
-            .await?;
+            .await
+            .context("delete workspace user")?;
+
+        let status = res.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(UserNotFound { email: user.to_owned() }.into());
+        }
+        if !status.is_success() {
+            let body = res.text().await.unwrap_or_default();
+            bail!("delete workspace user {user}: {status}: {body}");
+        }
 
         Ok(())
     }
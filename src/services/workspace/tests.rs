@@ -0,0 +1,62 @@
+use serde_json::json;
+
+use super::users::{ListUsersOptions, ListUsersOptionsBuilder, WorkspaceUserData};
+
+#[test]
+pub fn test_list_users_params_default_to_the_whole_account() {
+    let params = ListUsersOptions::default().query_params(None);
+
+    assert_eq!(
+        params,
+        vec![("maxResults", "500".to_owned()), ("customer", "my_customer".to_owned())]
+    );
+}
+
+#[test]
+pub fn test_list_users_params_combine_filters() {
+    let options = ListUsersOptionsBuilder::default()
+        .domain("example.org".to_owned())
+        .org_unit_path("/Volunteers/O'Hare".to_owned())
+        .query("isSuspended=false".to_owned())
+        .build()
+        .unwrap();
+
+    let params = options.query_params(Some("page2"));
+
+    assert_eq!(
+        params,
+        vec![
+            ("maxResults", "500".to_owned()),
+            ("domain", "example.org".to_owned()),
+            (
+                "query",
+                "orgUnitPath='/Volunteers/O\\'Hare' isSuspended=false".to_owned()
+            ),
+            ("pageToken", "page2".to_owned()),
+        ]
+    );
+}
+
+#[test]
+pub fn test_deserialize_sparse_users_page() {
+    let page = serde_json::from_value::<WorkspaceUserData>(json!({
+        "kind": "admin#directory#users",
+        "etag": "\"abc\"",
+        "users": [{
+            "id": "1001",
+            "primaryEmail": "ada@example.org",
+            "name": {"givenName": "Ada", "familyName": "Lovelace"},
+            "orgUnitPath": "/Volunteers"
+        }],
+        "nextPageToken": "page2"
+    }))
+    .unwrap();
+
+    assert_eq!(page.users[0].primary_email, "ada@example.org");
+    assert_eq!(page.users[0].recovery_email, None);
+    assert_eq!(page.next_page_token.as_deref(), Some("page2"));
+
+    let last =
+        serde_json::from_value::<WorkspaceUserData>(json!({"kind": "admin#directory#users", "etag": "x"})).unwrap();
+    assert!(last.users.is_empty());
+}
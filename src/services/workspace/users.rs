@@ -1,9 +1,10 @@
 use derive_builder::Builder;
 use serde::{Deserialize, Serialize};
 
+// The directory leaves out fields that are unset, such as `recoveryEmail` or `nonEditableAliases`.
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(default, rename_all = "camelCase")]
 pub struct WorkspaceUser {
     pub kind: String,
     pub id: String,
@@ -62,10 +63,58 @@ pub struct Language {
 pub type WorkspaceUsers = Vec<WorkspaceUser>;
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct WorkspaceUserData {
     pub kind: String,
     pub etag: String,
+    // left out of the last page when a listing is empty
+    #[serde(default)]
     pub users: WorkspaceUsers,
+    pub next_page_token: Option<String>,
+}
+
+// Narrows a directory listing to a domain, an organizational unit and the units under it, and a search in the
+// Directory API's query syntax, e.g. `isSuspended=false`. Without a domain every user of the account is listed.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Builder)]
+#[builder(default, setter(into))]
+#[serde(rename_all = "camelCase")]
+pub struct ListUsersOptions {
+    pub domain: Option<String>,
+    pub org_unit_path: Option<String>,
+    pub query: Option<String>,
+}
+
+impl ListUsersOptions {
+    const PAGE_SIZE: &'static str = "500";
+
+    pub fn query_params(&self, page_token: Option<&str>) -> Vec<(&'static str, String)> {
+        let mut params = vec![("maxResults", Self::PAGE_SIZE.to_owned())];
+
+        match &self.domain {
+            Some(domain) => params.push(("domain", domain.clone())),
+            None => params.push(("customer", "my_customer".to_owned())),
+        }
+
+        let query = [
+            self.org_unit_path
+                .as_ref()
+                .map(|path| format!("orgUnitPath='{}'", path.replace('\'', "\\'"))),
+            self.query.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<String>>()
+        .join(" ");
+
+        if !query.is_empty() {
+            params.push(("query", query));
+        }
+        if let Some(page_token) = page_token {
+            params.push(("pageToken", page_token.to_owned()));
+        }
+
+        params
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Builder)]
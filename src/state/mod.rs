@@ -1,14 +1,23 @@
 use anyhow::Error;
 use sendgrid::SGClient;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::Mutex;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{Mutex, Semaphore};
 
 use tokio::task::JoinHandle;
 
-use crate::services::{airtable::Airtable, auth::Authenticator, storage::Storage, workspace::WorkspaceClient};
+use crate::services::{
+    airtable::Airtable, auth::Authenticator, events::JobEvents, storage::Storage, workspace::WorkspaceClient,
+};
 
 type TaskMap = HashMap<String, Option<JoinHandle<()>>>;
 
+pub struct Settings {
+    pub idempotency_window: Duration,
+    pub airtable_cache_ttl: Duration,
+    pub stale_job_after: Duration,
+    pub public_base_uri: Option<String>,
+}
+
 pub struct State {
     pub authenticator: Box<dyn Authenticator>,
     pub workspace_client: Box<dyn WorkspaceClient>,
@@ -16,6 +25,9 @@ pub struct State {
     pub storage: Storage,
     pub tasks: Mutex<TaskMap>,
     pub mail: SGClient,
+    pub events: JobEvents,
+    pub workers: Arc<Semaphore>,
+    pub settings: Settings,
 }
 
 pub type AppState = Arc<State>;